@@ -0,0 +1,15 @@
+use chacha20_poly1305_rs::{Chacha20Poly1305, Error};
+
+pub fn main() -> Result<(), Error> {
+    let key: [u8; 32] = [0x42; 32];
+    let nonce: [u8; 12] = [0x24; 12];
+    let aad = b"example header";
+    let message = b"Attack at dawn";
+    let aead = Chacha20Poly1305::new(key);
+    let (ciphertext, tag) = aead.encrypt(&nonce, aad, message)?;
+    println!("ciphertext: {}", ciphertext.iter().fold(String::new(), |acc, &x| acc + &format!("{:02x}", &x)));
+    println!("tag:        {}", tag.iter().fold(String::new(), |acc, &x| acc + &format!("{:02x}", &x)));
+    let plaintext = aead.decrypt(&nonce, aad, &ciphertext, &tag)?;
+    println!("plaintext:  {}", String::from_utf8_lossy(&plaintext));
+    Ok(())
+}
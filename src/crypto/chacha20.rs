@@ -1,21 +1,51 @@
 use crate::crypto::utils;
+use crate::error::{Error, Result};
 
+use core::convert::TryInto;
 use std::vec::Vec;
 
 
-pub struct ChaCha20 {
+// RFC 8439 uses a 32-bit block counter and a 96-bit nonce; the original
+// layout from Bernstein's paper, still used by libsodium's non-IETF
+// construction, splits the same four words into a 64-bit counter and a
+// 64-bit nonce.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum StateLayout {
+    Ietf,
+    Legacy
+}
+
+// "expand 32-byte k" and "expand 16-byte k". A 16-byte key fills both halves
+// of the key words.
+const SIGMA: [u32; 4] = [0x61707865, 0x3320646E, 0x79622D32, 0x6B206574];
+const TAU: [u32; 4] = [0x61707865, 0x3120646E, 0x79622D36, 0x6B206574];
+
+/// The ChaCha stream cipher with `ROUNDS` rounds, which must be even.
+/// `ChaCha20` is the RFC 8439 cipher; `ChaCha12` and `ChaCha8` trade margin
+/// for speed and are not interchangeable with it.
+pub struct ChaCha<const ROUNDS: usize> {
+    constants: [u32; 4],
     key: [u8; 32],
     nonce: [u8; 12],
-    block_count: u32,
+    layout: StateLayout,
+    block_count: u64,
+    exhausted: bool,
     current_state: [u32; 16],
-    modulus: u64
+    modulus: u64,
+    keystream: [u8; 64],
+    keystream_index: usize
 }
 
-impl ChaCha20 {
+pub type ChaCha20 = ChaCha<20>;
+pub type ChaCha12 = ChaCha<12>;
+pub type ChaCha8 = ChaCha<8>;
 
-    fn create_state(key: [u8; 32], nonce: [u8; 12], block_count: u32) -> [u32; 16] {
+impl<const ROUNDS: usize> ChaCha<ROUNDS> {
+    const EVEN_ROUNDS: () = assert!(ROUNDS.is_multiple_of(2), "ChaCha needs an even number of rounds");
+
+    fn create_state(constants: [u32; 4], key: [u8; 32], nonce: [u8; 12], block_count: u32) -> [u32; 16] {
         let mut new_state: [u32; 16] = [
-            0x61707865, 0x3320646E, 0x79622D32, 0x6B206574,
+            constants[0], constants[1], constants[2], constants[3],
             0, 0, 0, 0,
             0, 0, 0, 0,
             block_count,
@@ -30,16 +60,24 @@ impl ChaCha20 {
         return new_state;
     }
 
-    fn update_state(&self, mut working_state: [u32; 16], a: usize, b: usize, c: usize, d: usize) -> [u32; 16] {
+    // The legacy nonce is held in the last 8 bytes of `nonce`; the high half of
+    // the counter takes the place of the first IETF nonce word.
+    fn create_legacy_state(constants: [u32; 4], key: [u8; 32], nonce: [u8; 12], block_count: u64) -> [u32; 16] {
+        let mut counter_nonce = nonce;
+        counter_nonce[0..4].copy_from_slice(&((block_count >> 32) as u32).to_le_bytes());
+        return Self::create_state(constants, key, counter_nonce, block_count as u32);
+    }
+
+    fn update_state(mut working_state: [u32; 16], a: usize, b: usize, c: usize, d: usize) -> [u32; 16] {
         let updated_points: [usize; 4] = [a, b, c, d];
-        let updated_set = self.quarter_round(working_state[a], working_state[b], working_state[c], working_state[d]);
+        let updated_set = Self::quarter_round(working_state[a], working_state[b], working_state[c], working_state[d]);
         for i in 0..4 {
             working_state[updated_points[i]] = updated_set[i];
         }
         return working_state;
     }
 
-    fn quarter_round(&self, mut a: u32, mut b: u32, mut c: u32, mut d: u32) -> [u32; 4] {
+    fn quarter_round(mut a: u32, mut b: u32, mut c: u32, mut d: u32) -> [u32; 4] {
         a = a.wrapping_add(b);
         d = (d ^ a).rotate_left(16);
         c = c.wrapping_add(d);
@@ -51,22 +89,54 @@ impl ChaCha20 {
         [a, b, c, d]
     }
 
-    pub fn chacha_block(&mut self) -> [u8; 64] {
-        self.current_state = Self::create_state(self.key, self.nonce, self.block_count);
-        let mut working_state = self.current_state;
-        let mut keystream: [u8; 64] = [0; 64];
-        for _ in 0..10 {
+    fn permute(mut working_state: [u32; 16]) -> [u32; 16] {
+        let () = Self::EVEN_ROUNDS;
+        for _ in 0..ROUNDS / 2 {
             // Horizontal round
-            working_state = self.update_state(working_state, 0, 4, 8, 12);
-            working_state = self.update_state(working_state, 1, 5, 9, 13);
-            working_state = self.update_state(working_state, 2, 6, 10, 14);
-            working_state = self.update_state(working_state, 3, 7, 11, 15);
+            working_state = Self::update_state(working_state, 0, 4, 8, 12);
+            working_state = Self::update_state(working_state, 1, 5, 9, 13);
+            working_state = Self::update_state(working_state, 2, 6, 10, 14);
+            working_state = Self::update_state(working_state, 3, 7, 11, 15);
             // Diagonal round
-            working_state = self.update_state(working_state, 0, 5, 10, 15);
-            working_state = self.update_state(working_state, 1, 6, 11, 12);
-            working_state = self.update_state(working_state, 2, 7, 8, 13);
-            working_state = self.update_state(working_state, 3, 4, 9, 14);
+            working_state = Self::update_state(working_state, 0, 5, 10, 15);
+            working_state = Self::update_state(working_state, 1, 6, 11, 12);
+            working_state = Self::update_state(working_state, 2, 7, 8, 13);
+            working_state = Self::update_state(working_state, 3, 4, 9, 14);
         }
+        return working_state;
+    }
+
+    // Keystream left in the current block plus the blocks still available
+    // before the counter runs out.
+    fn remaining_keystream(&self) -> u128 {
+        let remaining_blocks: u128 = if self.exhausted {
+            0
+        } else {
+            match self.layout {
+                StateLayout::Ietf => (1u128 << 32).saturating_sub(self.block_count as u128),
+                StateLayout::Legacy => (1u128 << 64) - self.block_count as u128,
+            }
+        };
+        (64 - self.keystream_index) as u128 + remaining_blocks * 64
+    }
+
+    /// Returns the next whole keystream block, discarding any unused bytes
+    /// buffered by `apply_keystream`.
+    pub fn chacha_block(&mut self) -> Result<[u8; 64]> {
+        self.keystream_index = 64;
+        self.next_block()
+    }
+
+    fn next_block(&mut self) -> Result<[u8; 64]> {
+        if self.exhausted || (self.layout == StateLayout::Ietf && self.block_count > u32::MAX as u64) {
+            return Err(Error::CounterExhausted);
+        }
+        self.current_state = match self.layout {
+            StateLayout::Ietf => Self::create_state(self.constants, self.key, self.nonce, self.block_count as u32),
+            StateLayout::Legacy => Self::create_legacy_state(self.constants, self.key, self.nonce, self.block_count),
+        };
+        let working_state = Self::permute(self.current_state);
+        let mut keystream: [u8; 64] = [0; 64];
         for i in 0..16 {
             let state_value = ((self.current_state[i] as u64 + working_state[i] as u64) % self.modulus) as u32;
             self.current_state[i] = state_value;
@@ -78,29 +148,586 @@ impl ChaCha20 {
                 keystream[keystream_index + n] = keystream_value[n];
             }
         }
-        self.block_count += 1;
-        return keystream;
+        match self.block_count.checked_add(1) {
+            Some(block_count) => self.block_count = block_count,
+            None => self.exhausted = true,
+        }
+        return Ok(keystream);
     }
 
-    pub fn encrypt_stream(&mut self, plaintext: Vec<u8>) -> Vec<u8> {
-        let mut ciphertext: Vec<u8> = Vec::new();
-        let mut keystream: [u8; 64] = [0; 64];
-        for i in 0..plaintext.len() {
-            if i % 64 == 0 {
-                keystream = self.chacha_block();
+    // The input states for the next `LANES` blocks, without advancing.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn lane_states<const LANES: usize>(&self) -> [[u32; 16]; LANES] {
+        let mut states: [[u32; 16]; LANES] = [[0; 16]; LANES];
+        for i in 0..LANES {
+            let block_count = self.block_count + i as u64;
+            states[i] = match self.layout {
+                StateLayout::Ietf => Self::create_state(self.constants, self.key, self.nonce, block_count as u32),
+                StateLayout::Legacy => Self::create_legacy_state(self.constants, self.key, self.nonce, block_count),
+            };
+        }
+        return states;
+    }
+
+    // Moves past `blocks` blocks produced outside `next_block`, ending in the
+    // same state `next_block` would have left.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn advance(&mut self, blocks: u64) {
+        match self.block_count.checked_add(blocks) {
+            Some(block_count) => self.block_count = block_count,
+            None => {
+                self.block_count = u64::MAX;
+                self.exhausted = true;
+            }
+        }
+    }
+
+    // Fills `keystream`, a whole number of blocks, with the widest backend
+    // the CPU supports: 8 blocks at a time with AVX2, 4 with SSE2, then one
+    // at a time. The caller must have checked that the counter does not run
+    // out.
+    fn fill_blocks(&mut self, keystream: &mut [u8]) -> Result<()> {
+        let mut offset = 0;
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                while keystream.len() - offset >= 8 * 64 {
+                    let states = self.lane_states::<8>();
+                    // SAFETY: AVX2 support was detected above, and the slice
+                    // is the 512 bytes blocks_avx2 writes.
+                    unsafe { x86::blocks_avx2::<ROUNDS>(&states, &mut keystream[offset..offset + 8 * 64]) };
+                    self.advance(8);
+                    offset += 8 * 64;
+                }
+            }
+            if is_x86_feature_detected!("sse2") {
+                while keystream.len() - offset >= 4 * 64 {
+                    let states = self.lane_states::<4>();
+                    // SAFETY: SSE2 support was detected above, and the slice
+                    // is the 256 bytes blocks_sse2 writes.
+                    unsafe { x86::blocks_sse2::<ROUNDS>(&states, &mut keystream[offset..offset + 4 * 64]) };
+                    self.advance(4);
+                    offset += 4 * 64;
+                }
             }
-            ciphertext.push(plaintext[i] ^ keystream[i % 64]);
         }
-        return ciphertext;
+        while offset < keystream.len() {
+            keystream[offset..offset + 64].copy_from_slice(&self.next_block()?);
+            offset += 64;
+        }
+        Ok(())
+    }
+
+    /// XORs the keystream into `buffer`, continuing from wherever the last
+    /// call stopped, even mid-block. Fails without touching `buffer` if the
+    /// block counter would run out part way through.
+    pub fn apply_keystream(&mut self, buffer: &mut [u8]) -> Result<()> {
+        if buffer.len() as u128 > self.remaining_keystream() {
+            return Err(Error::CounterExhausted);
+        }
+        let mut position = 0;
+        while position < buffer.len() {
+            if self.keystream_index == 64 && buffer.len() - position >= 4 * 64 {
+                let length = core::cmp::min((buffer.len() - position) / 64 * 64, 8 * 64);
+                let mut keystream: [u8; 8 * 64] = [0; 8 * 64];
+                self.fill_blocks(&mut keystream[..length])?;
+                for i in 0..length {
+                    buffer[position + i] ^= keystream[i];
+                }
+                position += length;
+                continue;
+            }
+            if self.keystream_index == 64 {
+                self.keystream = self.next_block()?;
+                self.keystream_index = 0;
+            }
+            buffer[position] ^= self.keystream[self.keystream_index];
+            self.keystream_index += 1;
+            position += 1;
+        }
+        Ok(())
+    }
+
+    /// Moves to `byte_offset` in the keystream, relative to block 0.
+    pub fn seek(&mut self, byte_offset: u64) -> Result<()> {
+        let block_count = byte_offset / 64;
+        let block_offset = (byte_offset % 64) as usize;
+        if self.layout == StateLayout::Ietf && (block_count > 1 << 32 || (block_count == 1 << 32 && block_offset > 0)) {
+            return Err(Error::CounterExhausted);
+        }
+        self.block_count = block_count;
+        self.exhausted = false;
+        self.keystream_index = 64;
+        if block_offset > 0 {
+            self.keystream = self.next_block()?;
+            self.keystream_index = block_offset;
+        }
+        Ok(())
     }
 
-    pub fn new(key: [u8; 32], nonce: [u8; 12]) -> ChaCha20 {
-        ChaCha20{
+    /// The byte offset of the next keystream byte, relative to block 0.
+    /// Legacy streams positioned beyond 2^64 bytes report `u64::MAX`.
+    pub fn current_pos(&self) -> u64 {
+        let mut block_count = self.block_count as u128;
+        if self.exhausted {
+            block_count = 1 << 64;
+        }
+        let position = block_count * 64 - (64 - self.keystream_index) as u128;
+        if position > u64::MAX as u128 {
+            return u64::MAX;
+        }
+        return position as u64;
+    }
+
+    pub fn encrypt_in_place(&mut self, buffer: &mut [u8]) -> Result<()> {
+        self.apply_keystream(buffer)
+    }
+
+    pub fn encrypt_stream(&mut self, plaintext: Vec<u8>) -> Result<Vec<u8>> {
+        let mut ciphertext = plaintext;
+        self.encrypt_in_place(&mut ciphertext)?;
+        return Ok(ciphertext);
+    }
+
+    pub fn new_from_slices(key: &[u8], nonce: &[u8]) -> Result<ChaCha<ROUNDS>> {
+        let key: [u8; 32] = key.try_into().map_err(|_| Error::InvalidKeyLength)?;
+        let nonce: [u8; 12] = nonce.try_into().map_err(|_| Error::InvalidNonceLength)?;
+        Ok(Self::new(key, nonce))
+    }
+
+    fn with_state(constants: [u32; 4], key: [u8; 32], nonce: [u8; 12], layout: StateLayout, counter: u64) -> ChaCha<ROUNDS> {
+        let current_state = match layout {
+            StateLayout::Ietf => Self::create_state(constants, key, nonce, counter as u32),
+            StateLayout::Legacy => Self::create_legacy_state(constants, key, nonce, counter),
+        };
+        ChaCha{
+            constants: constants,
             key: key,
             nonce: nonce,
-            block_count: 0,
-            current_state: ChaCha20::create_state(key, nonce, 0),
-            modulus: (2 as u64).pow(32)
+            layout: layout,
+            block_count: counter,
+            exhausted: false,
+            current_state: current_state,
+            modulus: 2u64.pow(32),
+            keystream: [0; 64],
+            keystream_index: 64
+        }
+    }
+
+    fn expand_128(key: [u8; 16]) -> [u8; 32] {
+        let mut expanded: [u8; 32] = [0; 32];
+        expanded[0..16].copy_from_slice(&key);
+        expanded[16..32].copy_from_slice(&key);
+        return expanded;
+    }
+
+    fn legacy_nonce(nonce: [u8; 8]) -> [u8; 12] {
+        let mut legacy_nonce: [u8; 12] = [0; 12];
+        legacy_nonce[4..12].copy_from_slice(&nonce);
+        return legacy_nonce;
+    }
+
+    pub fn new(key: [u8; 32], nonce: [u8; 12]) -> ChaCha<ROUNDS> {
+        Self::new_with_counter(key, nonce, 0)
+    }
+
+    /// ChaCha starting from block `counter`, as in the RFC 8439
+    /// `chacha20_encrypt(key, counter, nonce, plaintext)` interface.
+    pub fn new_with_counter(key: [u8; 32], nonce: [u8; 12], counter: u32) -> ChaCha<ROUNDS> {
+        Self::with_state(SIGMA, key, nonce, StateLayout::Ietf, counter as u64)
+    }
+
+    /// ChaCha with the original 64-bit nonce and 64-bit block counter.
+    pub fn new_legacy(key: [u8; 32], nonce: [u8; 8]) -> ChaCha<ROUNDS> {
+        Self::new_legacy_with_counter(key, nonce, 0)
+    }
+
+    pub fn new_legacy_with_counter(key: [u8; 32], nonce: [u8; 8], counter: u64) -> ChaCha<ROUNDS> {
+        Self::with_state(SIGMA, key, Self::legacy_nonce(nonce), StateLayout::Legacy, counter)
+    }
+
+    /// ChaCha with a 16-byte key and the "expand 16-byte k" constants.
+    pub fn new_128(key: [u8; 16], nonce: [u8; 12]) -> ChaCha<ROUNDS> {
+        Self::with_state(TAU, Self::expand_128(key), nonce, StateLayout::Ietf, 0)
+    }
+
+    pub fn new_legacy_128(key: [u8; 16], nonce: [u8; 8]) -> ChaCha<ROUNDS> {
+        Self::with_state(TAU, Self::expand_128(key), Self::legacy_nonce(nonce), StateLayout::Legacy, 0)
+    }
+}
+
+// Multi-block backends. Each vector register holds the same state word from
+// 4 (SSE2) or 8 (AVX2) consecutive blocks, so the rounds run on all blocks
+// at once and the results are transposed back into keystream order.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::*;
+
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn rotate_sse2<const LEFT: i32, const RIGHT: i32>(x: __m128i) -> __m128i {
+        _mm_or_si128(_mm_slli_epi32::<LEFT>(x), _mm_srli_epi32::<RIGHT>(x))
+    }
+
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn quarter_round_sse2(x: &mut [__m128i; 16], a: usize, b: usize, c: usize, d: usize) {
+        x[a] = _mm_add_epi32(x[a], x[b]);
+        x[d] = rotate_sse2::<16, 16>(_mm_xor_si128(x[d], x[a]));
+        x[c] = _mm_add_epi32(x[c], x[d]);
+        x[b] = rotate_sse2::<12, 20>(_mm_xor_si128(x[b], x[c]));
+        x[a] = _mm_add_epi32(x[a], x[b]);
+        x[d] = rotate_sse2::<8, 24>(_mm_xor_si128(x[d], x[a]));
+        x[c] = _mm_add_epi32(x[c], x[d]);
+        x[b] = rotate_sse2::<7, 25>(_mm_xor_si128(x[b], x[c]));
+    }
+
+    /// Writes the 4 blocks for `states` to `keystream`, which must be 256
+    /// bytes long. The caller must have checked that SSE2 is available.
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn blocks_sse2<const ROUNDS: usize>(states: &[[u32; 16]; 4], keystream: &mut [u8]) {
+        let mut x: [__m128i; 16] = [_mm_setzero_si128(); 16];
+        for i in 0..16 {
+            x[i] = _mm_set_epi32(states[3][i] as i32, states[2][i] as i32, states[1][i] as i32, states[0][i] as i32);
+        }
+        let original = x;
+        for _ in 0..ROUNDS / 2 {
+            quarter_round_sse2(&mut x, 0, 4, 8, 12);
+            quarter_round_sse2(&mut x, 1, 5, 9, 13);
+            quarter_round_sse2(&mut x, 2, 6, 10, 14);
+            quarter_round_sse2(&mut x, 3, 7, 11, 15);
+            quarter_round_sse2(&mut x, 0, 5, 10, 15);
+            quarter_round_sse2(&mut x, 1, 6, 11, 12);
+            quarter_round_sse2(&mut x, 2, 7, 8, 13);
+            quarter_round_sse2(&mut x, 3, 4, 9, 14);
+        }
+        for i in 0..16 {
+            let mut lanes: [u32; 4] = [0; 4];
+            _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, _mm_add_epi32(x[i], original[i]));
+            for lane in 0..4 {
+                let index = lane * 64 + i * 4;
+                keystream[index..index + 4].copy_from_slice(&lanes[lane].to_le_bytes());
+            }
+        }
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn rotate_avx2<const LEFT: i32, const RIGHT: i32>(x: __m256i) -> __m256i {
+        _mm256_or_si256(_mm256_slli_epi32::<LEFT>(x), _mm256_srli_epi32::<RIGHT>(x))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn quarter_round_avx2(x: &mut [__m256i; 16], a: usize, b: usize, c: usize, d: usize) {
+        x[a] = _mm256_add_epi32(x[a], x[b]);
+        x[d] = rotate_avx2::<16, 16>(_mm256_xor_si256(x[d], x[a]));
+        x[c] = _mm256_add_epi32(x[c], x[d]);
+        x[b] = rotate_avx2::<12, 20>(_mm256_xor_si256(x[b], x[c]));
+        x[a] = _mm256_add_epi32(x[a], x[b]);
+        x[d] = rotate_avx2::<8, 24>(_mm256_xor_si256(x[d], x[a]));
+        x[c] = _mm256_add_epi32(x[c], x[d]);
+        x[b] = rotate_avx2::<7, 25>(_mm256_xor_si256(x[b], x[c]));
+    }
+
+    /// Writes the 8 blocks for `states` to `keystream`, which must be 512
+    /// bytes long. The caller must have checked that AVX2 is available.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn blocks_avx2<const ROUNDS: usize>(states: &[[u32; 16]; 8], keystream: &mut [u8]) {
+        let mut x: [__m256i; 16] = [_mm256_setzero_si256(); 16];
+        for i in 0..16 {
+            x[i] = _mm256_set_epi32(
+                states[7][i] as i32, states[6][i] as i32, states[5][i] as i32, states[4][i] as i32,
+                states[3][i] as i32, states[2][i] as i32, states[1][i] as i32, states[0][i] as i32
+            );
+        }
+        let original = x;
+        for _ in 0..ROUNDS / 2 {
+            quarter_round_avx2(&mut x, 0, 4, 8, 12);
+            quarter_round_avx2(&mut x, 1, 5, 9, 13);
+            quarter_round_avx2(&mut x, 2, 6, 10, 14);
+            quarter_round_avx2(&mut x, 3, 7, 11, 15);
+            quarter_round_avx2(&mut x, 0, 5, 10, 15);
+            quarter_round_avx2(&mut x, 1, 6, 11, 12);
+            quarter_round_avx2(&mut x, 2, 7, 8, 13);
+            quarter_round_avx2(&mut x, 3, 4, 9, 14);
+        }
+        for i in 0..16 {
+            let mut lanes: [u32; 8] = [0; 8];
+            _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, _mm256_add_epi32(x[i], original[i]));
+            for lane in 0..8 {
+                let index = lane * 64 + i * 4;
+                keystream[index..index + 4].copy_from_slice(&lanes[lane].to_le_bytes());
+            }
+        }
+    }
+}
+
+/// HChaCha20 (draft-irtf-cfrg-xchacha section 2.2): the ChaCha20 permutation
+/// over a 16-byte input in place of the counter and nonce, without the final
+/// addition, returning words 0..4 and 12..16. It is a PRF on `input`, so it
+/// can derive independent subkeys from one key.
+pub fn hchacha20(key: [u8; 32], input: [u8; 16]) -> [u8; 32] {
+    let mut nonce: [u8; 12] = [0; 12];
+    nonce.copy_from_slice(&input[4..16]);
+    let working_state = ChaCha20::permute(ChaCha20::create_state(SIGMA, key, nonce, utils::bytes_to_word(&input[0..4])));
+    let mut subkey: [u8; 32] = [0; 32];
+    for i in 0..4 {
+        subkey[i * 4..i * 4 + 4].copy_from_slice(&working_state[i].to_le_bytes());
+        subkey[16 + i * 4..16 + i * 4 + 4].copy_from_slice(&working_state[12 + i].to_le_bytes());
+    }
+    return subkey;
+}
+
+fn first_half(block: Result<[u8; 64]>) -> [u8; 32] {
+    // Block 0 is always within the counter range.
+    let block = match block {
+        Ok(block) => block,
+        Err(_) => unreachable!(),
+    };
+    let mut half: [u8; 32] = [0; 32];
+    half.copy_from_slice(&block[0..32]);
+    return half;
+}
+
+/// The Poly1305 one-time key for `nonce` (RFC 8439 section 2.6): the first 32
+/// bytes of ChaCha20 block 0. Encryption then starts at block 1.
+pub fn poly1305_key_gen(key: [u8; 32], nonce: [u8; 12]) -> [u8; 32] {
+    first_half(ChaCha20::new(key, nonce).chacha_block())
+}
+
+/// `poly1305_key_gen` for the original 64-bit nonce layout.
+pub fn poly1305_key_gen_legacy(key: [u8; 32], nonce: [u8; 8]) -> [u8; 32] {
+    first_half(ChaCha20::new_legacy(key, nonce).chacha_block())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+        0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+        0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+        0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f
+    ];
+    const NONCE: [u8; 12] = [0, 0, 0, 0x09, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+
+    fn keystream<const ROUNDS: usize>(cipher: &mut ChaCha<ROUNDS>, length: usize) -> Vec<u8> {
+        let mut buffer = vec![0; length];
+        cipher.apply_keystream(&mut buffer).unwrap();
+        return buffer;
+    }
+
+    // Keystream for an all-zero key and nonce, from Strömbergson's
+    // draft-strombergson-chacha-test-vectors TC1.
+    #[test]
+    fn reduced_round_test_vectors() {
+        let hex = crate::crypto::utils::hex_to_bytes;
+        assert_eq!(keystream(&mut ChaCha8::new_legacy_128([0; 16], [0; 8]), 32), hex("e28a5fa4a67f8c5defed3e6fb7303486aa8427d31419a729572d777953491120"));
+        assert_eq!(keystream(&mut ChaCha8::new_legacy([0; 32], [0; 8]), 32), hex("3e00ef2f895f40d67f5bb8e81f09a5a12c840ec3ce9a7f3b181be188ef711a1e"));
+        assert_eq!(keystream(&mut ChaCha12::new_legacy_128([0; 16], [0; 8]), 32), hex("e1047ba9476bf8ff312c01b4345a7d8ca5792b0ad467313f1dc412b5fdce3241"));
+        assert_eq!(keystream(&mut ChaCha12::new_legacy([0; 32], [0; 8]), 32), hex("9bf49a6a0755f953811fce125f2683d50429c3bb49e074147e0089a52eae155f"));
+        assert_eq!(keystream(&mut ChaCha20::new_legacy_128([0; 16], [0; 8]), 32), hex("89670952608364fd00b2f90936f031c8e756e15dba04b8493d00429259b20f46"));
+        assert_eq!(keystream(&mut ChaCha20::new_legacy([0; 32], [0; 8]), 32), hex("76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7"));
+
+        // With a zero nonce and counter the IETF layout gives the same block.
+        assert_eq!(keystream(&mut ChaCha8::new_128([0; 16], [0; 12]), 64), keystream(&mut ChaCha8::new_legacy_128([0; 16], [0; 8]), 64));
+        assert_eq!(keystream(&mut ChaCha12::new([0; 32], [0; 12]), 64), keystream(&mut ChaCha12::new_legacy([0; 32], [0; 8]), 64));
+    }
+
+    // A small xorshift generator, so the split points vary but are the
+    // same on every run.
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        return *state;
+    }
+
+    // Block-at-a-time keystream, which never takes the multi-block path.
+    fn reference_keystream<const ROUNDS: usize>(mut cipher: ChaCha<ROUNDS>, length: usize) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::new();
+        while output.len() < length {
+            output.extend_from_slice(&cipher.chacha_block().unwrap());
+        }
+        output.truncate(length);
+        return output;
+    }
+
+    fn check_against_reference<const ROUNDS: usize>(new_cipher: &dyn Fn() -> ChaCha<ROUNDS>, length: usize, random: &mut u64) {
+        let expected = reference_keystream(new_cipher(), length);
+        assert_eq!(keystream(&mut new_cipher(), length), expected, "{} rounds, {} bytes", ROUNDS, length);
+
+        let mut cipher = new_cipher();
+        let mut output: Vec<u8> = Vec::new();
+        while output.len() < length {
+            let chunk_length = core::cmp::min((next_random(random) % 700) as usize, length - output.len());
+            output.extend_from_slice(&keystream(&mut cipher, chunk_length));
+        }
+        assert_eq!(output, expected, "{} rounds, {} bytes split", ROUNDS, length);
+    }
+
+    fn multi_block_matches_reference<const ROUNDS: usize>() {
+        let lengths: [usize; 12] = [0, 1, 63, 64, 255, 256, 257, 511, 512, 513, 3000, 4099];
+        let mut random: u64 = 0x2545f4914f6cdd1d;
+        for length in lengths.iter() {
+            check_against_reference::<ROUNDS>(&|| ChaCha::new(KEY, NONCE), *length, &mut random);
+            check_against_reference::<ROUNDS>(&|| ChaCha::new_legacy_128([7; 16], [3; 8]), *length, &mut random);
+        }
+
+        // The IETF counter ends at u32::MAX; the legacy one carries past it,
+        // and ends at u64::MAX.
+        for blocks_left in [1, 4, 8, 9, 13].iter() {
+            let counter = u32::MAX - (*blocks_left - 1);
+            check_against_reference::<ROUNDS>(&|| ChaCha::new_with_counter(KEY, NONCE, counter), *blocks_left as usize * 64, &mut random);
+            let mut cipher = ChaCha::<ROUNDS>::new_with_counter(KEY, NONCE, counter);
+            assert!(cipher.apply_keystream(&mut vec![0; *blocks_left as usize * 64 + 1]).is_err());
+            check_against_reference::<ROUNDS>(&|| ChaCha::new_legacy_with_counter(KEY, [1; 8], counter as u64), 1000, &mut random);
+            let counter = u64::MAX - (*blocks_left as u64 - 1);
+            check_against_reference::<ROUNDS>(&|| ChaCha::new_legacy_with_counter(KEY, [1; 8], counter), *blocks_left as usize * 64, &mut random);
+        }
+    }
+
+    #[test]
+    fn chacha8_multi_block_matches_reference() {
+        multi_block_matches_reference::<8>();
+    }
+
+    #[test]
+    fn chacha12_multi_block_matches_reference() {
+        multi_block_matches_reference::<12>();
+    }
+
+    #[test]
+    fn chacha20_multi_block_matches_reference() {
+        multi_block_matches_reference::<20>();
+    }
+
+    // Calls each backend the CPU supports directly, so a machine with AVX2
+    // still checks the SSE2 path.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn backends_match_reference() {
+        let cipher = ChaCha20::new_with_counter(KEY, NONCE, 5);
+        let expected = reference_keystream(ChaCha20::new_with_counter(KEY, NONCE, 5), 8 * 64);
+        if is_x86_feature_detected!("sse2") {
+            let mut output: [u8; 4 * 64] = [0; 4 * 64];
+            // SAFETY: SSE2 support was just detected and the buffer is 256 bytes.
+            unsafe { x86::blocks_sse2::<20>(&cipher.lane_states::<4>(), &mut output) };
+            assert_eq!(output.to_vec(), expected[..4 * 64].to_vec());
+        }
+        if is_x86_feature_detected!("avx2") {
+            let mut output: [u8; 8 * 64] = [0; 8 * 64];
+            // SAFETY: AVX2 support was just detected and the buffer is 512 bytes.
+            unsafe { x86::blocks_avx2::<20>(&cipher.lane_states::<8>(), &mut output) };
+            assert_eq!(output.to_vec(), expected);
+        }
+    }
+
+    #[test]
+    fn split_calls_match_one_call() {
+        let expected = keystream(&mut ChaCha20::new(KEY, NONCE), 1500);
+        let splits: [&[usize]; 4] = [&[1, 62, 1, 300, 1136], &[63, 65, 127, 129, 1116], &[7; 214], &[500, 3, 997]];
+        for lengths in splits.iter() {
+            let mut cipher = ChaCha20::new(KEY, NONCE);
+            let mut output: Vec<u8> = Vec::new();
+            for length in lengths.iter() {
+                output.extend_from_slice(&keystream(&mut cipher, *length));
+            }
+            assert_eq!(output, expected[..output.len()].to_vec());
+            assert_eq!(cipher.current_pos(), output.len() as u64);
+        }
+    }
+
+    #[test]
+    fn seek_back_and_forth() {
+        let expected = keystream(&mut ChaCha20::new(KEY, NONCE), 2048);
+        let mut cipher = ChaCha20::new(KEY, NONCE);
+        for offset in [1000, 3, 64, 0, 1999, 129, 640].iter() {
+            cipher.seek(*offset as u64).unwrap();
+            assert_eq!(cipher.current_pos(), *offset as u64);
+            assert_eq!(keystream(&mut cipher, 45), expected[*offset..*offset + 45].to_vec());
+            assert_eq!(cipher.current_pos(), *offset as u64 + 45);
+        }
+
+        // Seeking works the same from a starting counter of 1.
+        let mut cipher = ChaCha20::new_with_counter(KEY, NONCE, 1);
+        assert_eq!(cipher.current_pos(), 64);
+        assert_eq!(keystream(&mut cipher, 100), expected[64..164].to_vec());
+        cipher.seek(10).unwrap();
+        assert_eq!(keystream(&mut cipher, 100), expected[10..110].to_vec());
+    }
+
+    #[test]
+    fn current_pos_after_whole_blocks() {
+        let mut cipher = ChaCha20::new(KEY, NONCE);
+        assert_eq!(cipher.current_pos(), 0);
+        cipher.chacha_block().unwrap();
+        assert_eq!(cipher.current_pos(), 64);
+        keystream(&mut cipher, 10);
+        assert_eq!(cipher.current_pos(), 74);
+        // chacha_block drops the rest of the buffered block.
+        cipher.chacha_block().unwrap();
+        assert_eq!(cipher.current_pos(), 192);
+    }
+
+    // The 32-bit IETF counter covers 2^32 blocks, 2^38 bytes.
+    #[test]
+    fn ietf_keystream_ends_at_2_38_bytes() {
+        let end: u64 = 1 << 38;
+        let mut cipher = ChaCha20::new(KEY, NONCE);
+        assert!(cipher.seek(end + 1).is_err());
+        cipher.seek(end).unwrap();
+        assert_eq!(cipher.current_pos(), end);
+        let mut buffer: [u8; 1] = [0];
+        assert_eq!(cipher.apply_keystream(&mut buffer), Err(Error::CounterExhausted));
+        assert!(cipher.chacha_block().is_err());
+
+        cipher.seek(end - 100).unwrap();
+        let mut buffer: [u8; 101] = [0; 101];
+        assert_eq!(cipher.apply_keystream(&mut buffer), Err(Error::CounterExhausted));
+        assert_eq!(buffer, [0; 101]);
+        assert_eq!(cipher.current_pos(), end - 100);
+        cipher.apply_keystream(&mut buffer[..100]).unwrap();
+        assert_eq!(cipher.current_pos(), end);
+
+        let mut cipher = ChaCha20::new_with_counter(KEY, NONCE, u32::MAX);
+        assert_eq!(keystream(&mut cipher, 64).len(), 64);
+        assert!(cipher.apply_keystream(&mut [0]).is_err());
+    }
+
+    #[test]
+    fn legacy_keystream_ends_at_2_70_bytes() {
+        let mut cipher = ChaCha20::new_legacy_with_counter(KEY, [0; 8], u64::MAX);
+        // Positions past 2^64 bytes saturate.
+        assert_eq!(cipher.current_pos(), u64::MAX);
+        keystream(&mut cipher, 64);
+        assert_eq!(cipher.current_pos(), u64::MAX);
+        assert!(cipher.apply_keystream(&mut [0]).is_err());
+        assert!(cipher.chacha_block().is_err());
+
+        // The legacy counter carries into its second word where the IETF
+        // counter would stop.
+        let mut cipher = ChaCha20::new_legacy_with_counter(KEY, [0; 8], u32::MAX as u64);
+        let mut expected = ChaCha20::new_legacy_with_counter(KEY, [0; 8], 1 << 32);
+        keystream(&mut cipher, 64);
+        assert_eq!(keystream(&mut cipher, 64), keystream(&mut expected, 64));
+    }
+
+
+    // RFC 8439 section 2.6.2.
+    #[test]
+    fn poly1305_key_gen_test_vector() {
+        let hex = crate::crypto::utils::hex_to_bytes;
+        let mut key: [u8; 32] = [0; 32];
+        for i in 0..32 {
+            key[i] = 0x80 + i as u8;
         }
+        let nonce: [u8; 12] = [0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(poly1305_key_gen(key, nonce).to_vec(), hex("8ad5a08b905f81cc815040274ab29471a833b637e3fd0da508dbb8e2fdd1a646"));
     }
 }
@@ -0,0 +1,486 @@
+use crate::crypto::poly1305::Poly1305;
+use crate::crypto::chacha20::{poly1305_key_gen, ChaCha20};
+use crate::crypto::utils;
+use crate::error::{Error, Result};
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+pub use crate::crypto::poly1305::Tag;
+
+/// A growable byte buffer that an appended tag can be written into.
+///
+/// Implemented for `Vec<u8>`; fixed-capacity buffers can implement it to
+/// seal and open messages without touching the heap.
+pub trait Buffer: AsRef<[u8]> + AsMut<[u8]> {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn extend_from_slice(&mut self, other: &[u8]) -> Result<()>;
+
+    fn truncate(&mut self, len: usize);
+}
+
+impl Buffer for Vec<u8> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn extend_from_slice(&mut self, other: &[u8]) -> Result<()> {
+        Vec::extend_from_slice(self, other);
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len);
+    }
+}
+
+pub struct Chacha20Poly1305 {
+    key: [u8; 32],
+}
+
+impl Chacha20Poly1305 {
+    // Block 0 keys Poly1305, leaving 2^32 - 1 blocks of keystream for the message.
+    const MAX_MESSAGE_LENGTH: u64 = (u32::MAX as u64) * 64;
+
+    pub fn encrypt(&self, nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, Tag)> {
+        let mut ciphertext = plaintext.to_vec();
+        let tag = self.encrypt_in_place_detached(nonce, aad, &mut ciphertext)?;
+        Ok((ciphertext, tag))
+    }
+
+    pub fn decrypt(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+        let tag: Tag = tag.try_into().map_err(|_| Error::InvalidTagLength)?;
+        let mut plaintext = ciphertext.to_vec();
+        self.decrypt_in_place_detached(nonce, aad, &mut plaintext, &tag)?;
+        Ok(plaintext)
+    }
+
+    /// Encrypts `buffer` in place and appends the tag to it.
+    pub fn encrypt_in_place<B: Buffer>(&self, nonce: &[u8; 12], aad: &[u8], buffer: &mut B) -> Result<()> {
+        let tag = self.encrypt_in_place_detached(nonce, aad, buffer.as_mut())?;
+        buffer.extend_from_slice(&tag)
+    }
+
+    /// Verifies and strips the tag at the end of `buffer`, then decrypts the
+    /// rest of it in place. The buffer is left untouched if verification fails.
+    pub fn decrypt_in_place<B: Buffer>(&self, nonce: &[u8; 12], aad: &[u8], buffer: &mut B) -> Result<()> {
+        if buffer.len() < 16 {
+            return Err(Error::InvalidTagLength);
+        }
+        let tag_index = buffer.len() - 16;
+        let (ciphertext, tag) = buffer.as_mut().split_at_mut(tag_index);
+        let tag: Tag = (&*tag).try_into().map_err(|_| Error::InvalidTagLength)?;
+        self.decrypt_in_place_detached(nonce, aad, ciphertext, &tag)?;
+        buffer.truncate(tag_index);
+        Ok(())
+    }
+
+    /// Starts an incremental encryption under `nonce`; see [`Encryptor`].
+    pub fn begin_encrypt(&self, nonce: &[u8; 12]) -> Result<Encryptor> {
+        Ok(Encryptor {
+            state: StreamState::new(self.key, nonce)?,
+        })
+    }
+
+    /// Starts an incremental decryption under `nonce`; see [`Decryptor`].
+    pub fn begin_decrypt(&self, nonce: &[u8; 12]) -> Result<Decryptor> {
+        Ok(Decryptor {
+            state: StreamState::new(self.key, nonce)?,
+        })
+    }
+
+    pub fn encrypt_in_place_detached(&self, nonce: &[u8; 12], aad: &[u8], buffer: &mut [u8]) -> Result<Tag> {
+        Self::check_length(buffer.len())?;
+        let mac_key = poly1305_key_gen(self.key, *nonce);
+        ChaCha20::new_with_counter(self.key, *nonce, 1).encrypt_in_place(buffer)?;
+        Ok(Self::compute_tag(mac_key, aad, buffer))
+    }
+
+    pub fn decrypt_in_place_detached(&self, nonce: &[u8; 12], aad: &[u8], buffer: &mut [u8], tag: &Tag) -> Result<()> {
+        Self::check_length(buffer.len())?;
+        let mac_key = poly1305_key_gen(self.key, *nonce);
+        let tag_2 = Self::compute_tag(mac_key, aad, buffer);
+        if !utils::constant_time_eq(tag, &tag_2) {
+            return Err(Error::AuthenticationFailed);
+        }
+        ChaCha20::new_with_counter(self.key, *nonce, 1).encrypt_in_place(buffer)
+    }
+
+    fn check_length(length: usize) -> Result<()> {
+        if length as u64 > Self::MAX_MESSAGE_LENGTH {
+            return Err(Error::MessageTooLong);
+        }
+        Ok(())
+    }
+
+    // ChaCha20 block 0 for `nonce`. RFC 8439 keys Poly1305 with its first
+    // half and discards the second.
+    pub(crate) fn first_block(&self, nonce: &[u8; 12]) -> Result<[u8; 64]> {
+        return ChaCha20::new(self.key, *nonce).chacha_block();
+    }
+
+    pub(crate) fn compute_tag(mac_key: [u8; 32], aad: &[u8], ciphertext: &[u8]) -> Tag {
+        let mut poly1305 = Poly1305::new(mac_key);
+        let mut lengths: [u8; 16] = [0; 16];
+        lengths[..8].copy_from_slice(&(aad.len() as u64).to_le_bytes());
+        lengths[8..].copy_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+        poly1305.update_padded(aad);
+        poly1305.update_padded(ciphertext);
+        poly1305.update_padded(&lengths);
+        poly1305.finalize()
+    }
+
+    pub fn new_from_slice(key: &[u8]) -> Result<Chacha20Poly1305> {
+        let key: [u8; 32] = key.try_into().map_err(|_| Error::InvalidKeyLength)?;
+        Ok(Chacha20Poly1305::new(key))
+    }
+
+    pub fn new(key: [u8; 32]) -> Chacha20Poly1305 {
+        Chacha20Poly1305 {
+            key: key,
+        }
+    }
+}
+
+// Keystream and MAC state shared by the incremental encryptor and decryptor.
+// The cipher carries partial keystream blocks and Poly1305 carries partial
+// MAC blocks between calls, so callers may split the AAD and message at any
+// byte boundary.
+struct StreamState {
+    cipher: ChaCha20,
+    poly1305: Poly1305,
+    aad_length: u64,
+    message_length: u64,
+    aad_finished: bool,
+}
+
+impl StreamState {
+    fn new(key: [u8; 32], nonce: &[u8; 12]) -> Result<StreamState> {
+        Ok(StreamState {
+            cipher: ChaCha20::new_with_counter(key, *nonce, 1),
+            poly1305: Poly1305::new(poly1305_key_gen(key, *nonce)),
+            aad_length: 0,
+            message_length: 0,
+            aad_finished: false,
+        })
+    }
+
+    fn update_aad(&mut self, aad: &[u8]) -> Result<()> {
+        if self.aad_finished {
+            return Err(Error::AadAfterMessage);
+        }
+        self.poly1305.update(aad);
+        self.aad_length += aad.len() as u64;
+        Ok(())
+    }
+
+    // Closes the AAD on the first call and accounts for the next `length`
+    // bytes of message.
+    fn begin_message(&mut self, length: usize) -> Result<()> {
+        let message_length = self.message_length + length as u64;
+        if message_length > Chacha20Poly1305::MAX_MESSAGE_LENGTH {
+            return Err(Error::MessageTooLong);
+        }
+        if !self.aad_finished {
+            self.poly1305.update_padded(&[]);
+            self.aad_finished = true;
+        }
+        self.message_length = message_length;
+        Ok(())
+    }
+
+    fn tag(mut self) -> Tag {
+        self.poly1305.update_padded(&[]);
+        let mut lengths: [u8; 16] = [0; 16];
+        lengths[..8].copy_from_slice(&self.aad_length.to_le_bytes());
+        lengths[8..].copy_from_slice(&self.message_length.to_le_bytes());
+        self.poly1305.update_padded(&lengths);
+        self.poly1305.finalize()
+    }
+}
+
+/// Incremental ChaCha20-Poly1305 encryption for messages too large to hold
+/// in memory. All AAD must be supplied before the first call to `update`.
+pub struct Encryptor {
+    state: StreamState,
+}
+
+impl Encryptor {
+    pub fn update_aad(&mut self, aad: &[u8]) -> Result<()> {
+        self.state.update_aad(aad)
+    }
+
+    /// Encrypts the next piece of the message in place.
+    pub fn update(&mut self, buffer: &mut [u8]) -> Result<()> {
+        self.state.begin_message(buffer.len())?;
+        self.state.cipher.apply_keystream(buffer)?;
+        self.state.poly1305.update(buffer);
+        Ok(())
+    }
+
+    pub fn finalize(self) -> Tag {
+        self.state.tag()
+    }
+}
+
+/// Incremental ChaCha20-Poly1305 decryption. Plaintext is released before the
+/// tag has been checked, so it must not be acted on until `finalize_verify`
+/// succeeds.
+pub struct Decryptor {
+    state: StreamState,
+}
+
+impl Decryptor {
+    pub fn update_aad(&mut self, aad: &[u8]) -> Result<()> {
+        self.state.update_aad(aad)
+    }
+
+    /// Decrypts the next piece of the ciphertext in place.
+    pub fn update(&mut self, buffer: &mut [u8]) -> Result<()> {
+        self.state.begin_message(buffer.len())?;
+        self.state.poly1305.update(buffer);
+        self.state.cipher.apply_keystream(buffer)
+    }
+
+    pub fn finalize_verify(self, tag: &Tag) -> Result<()> {
+        let tag_2 = self.state.tag();
+        if !utils::constant_time_eq(tag, &tag_2) {
+            return Err(Error::AuthenticationFailed);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+
+    // RFC 8439 section 2.8.2.
+    const KEY: &str = "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f";
+    const NONCE: [u8; 12] = [0x07, 0x00, 0x00, 0x00, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47];
+    const AAD: &str = "50515253c0c1c2c3c4c5c6c7";
+    const PLAINTEXT: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+    const CIPHERTEXT: &str = "
+        d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6
+        3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36
+        92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc
+        3ff4def08e4b7a9de576d26586cec64b6116";
+    const TAG: &str = "1ae10b594f09e26a7e902ecbd0600691";
+
+    fn aead() -> Chacha20Poly1305 {
+        Chacha20Poly1305::new_from_slice(&hex_to_bytes(KEY)).unwrap()
+    }
+
+    #[test]
+    fn rfc_8439_test_vector() {
+        let (ciphertext, tag) = aead().encrypt(&NONCE, &hex_to_bytes(AAD), PLAINTEXT).unwrap();
+        assert_eq!(ciphertext, hex_to_bytes(CIPHERTEXT));
+        assert_eq!(tag.to_vec(), hex_to_bytes(TAG));
+        assert_eq!(aead().decrypt(&NONCE, &hex_to_bytes(AAD), &ciphertext, &tag).unwrap(), PLAINTEXT);
+    }
+
+    #[test]
+    fn one_key_many_nonces() {
+        let aead = aead();
+        let (first, first_tag) = aead.encrypt(&[1; 12], b"", b"message").unwrap();
+        let (second, second_tag) = aead.encrypt(&[2; 12], b"", b"message").unwrap();
+        assert_ne!(first, second);
+        assert_ne!(first_tag, second_tag);
+        assert_eq!(aead.decrypt(&[2; 12], b"", &second, &second_tag).unwrap(), b"message");
+        assert_eq!(aead.decrypt(&[1; 12], b"", &first, &first_tag).unwrap(), b"message");
+    }
+
+    #[test]
+    fn typed_errors() {
+        let aead = aead();
+        let (ciphertext, tag) = aead.encrypt(&NONCE, b"aad", b"message").unwrap();
+        assert_eq!(aead.decrypt(&NONCE, b"aad", &ciphertext, &tag[..15]), Err(Error::InvalidTagLength));
+        assert_eq!(aead.decrypt(&NONCE, b"aad", &ciphertext, &[0; 17]), Err(Error::InvalidTagLength));
+        assert_eq!(aead.decrypt(&NONCE, b"aaD", &ciphertext, &tag), Err(Error::AuthenticationFailed));
+        assert_eq!(aead.decrypt(&[0; 12], b"aad", &ciphertext, &tag), Err(Error::AuthenticationFailed));
+        let mut bad_ciphertext = ciphertext.clone();
+        bad_ciphertext[0] ^= 1;
+        assert_eq!(aead.decrypt(&NONCE, b"aad", &bad_ciphertext, &tag), Err(Error::AuthenticationFailed));
+        assert!(matches!(Chacha20Poly1305::new_from_slice(&[0; 31]), Err(Error::InvalidKeyLength)));
+    }
+
+    // A fixed-capacity buffer, to check the in-place API works without Vec.
+    struct ArrayBuffer {
+        bytes: [u8; 64],
+        length: usize,
+    }
+
+    impl AsRef<[u8]> for ArrayBuffer {
+        fn as_ref(&self) -> &[u8] {
+            &self.bytes[..self.length]
+        }
+    }
+
+    impl AsMut<[u8]> for ArrayBuffer {
+        fn as_mut(&mut self) -> &mut [u8] {
+            &mut self.bytes[..self.length]
+        }
+    }
+
+    impl Buffer for ArrayBuffer {
+        fn len(&self) -> usize {
+            self.length
+        }
+
+        fn extend_from_slice(&mut self, other: &[u8]) -> Result<()> {
+            if self.length + other.len() > self.bytes.len() {
+                return Err(Error::MessageTooLong);
+            }
+            self.bytes[self.length..self.length + other.len()].copy_from_slice(other);
+            self.length += other.len();
+            Ok(())
+        }
+
+        fn truncate(&mut self, len: usize) {
+            self.length = core::cmp::min(self.length, len);
+        }
+    }
+
+    #[test]
+    fn in_place_matches_one_shot() {
+        let aead = aead();
+        let aad = hex_to_bytes(AAD);
+        let mut buffer = PLAINTEXT.to_vec();
+        aead.encrypt_in_place(&NONCE, &aad, &mut buffer).unwrap();
+        let mut expected = hex_to_bytes(CIPHERTEXT);
+        expected.extend_from_slice(&hex_to_bytes(TAG));
+        assert_eq!(buffer, expected);
+        aead.decrypt_in_place(&NONCE, &aad, &mut buffer).unwrap();
+        assert_eq!(buffer, PLAINTEXT);
+
+        let mut buffer = ArrayBuffer { bytes: [0; 64], length: 5 };
+        buffer.bytes[..5].copy_from_slice(b"hello");
+        aead.encrypt_in_place(&NONCE, b"", &mut buffer).unwrap();
+        assert_eq!(buffer.len(), 5 + 16);
+        aead.decrypt_in_place(&NONCE, b"", &mut buffer).unwrap();
+        assert_eq!(buffer.as_ref(), b"hello");
+    }
+
+    #[test]
+    fn decrypt_in_place_rejects_short_buffers() {
+        for length in 0..16 {
+            let mut buffer = vec![0; length];
+            assert_eq!(aead().decrypt_in_place(&NONCE, b"", &mut buffer), Err(Error::InvalidTagLength));
+            assert_eq!(buffer.len(), length);
+        }
+        // A bare tag is a valid encryption of the empty message.
+        let mut buffer: Vec<u8> = Vec::new();
+        aead().encrypt_in_place(&NONCE, b"", &mut buffer).unwrap();
+        assert_eq!(buffer.len(), 16);
+        aead().decrypt_in_place(&NONCE, b"", &mut buffer).unwrap();
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn failed_decryption_leaves_the_buffer_untouched() {
+        let aead = aead();
+        let mut buffer = b"in place".to_vec();
+        aead.encrypt_in_place(&NONCE, b"aad", &mut buffer).unwrap();
+        let last = buffer.len() - 1;
+        buffer[last] ^= 1;
+        let sealed = buffer.clone();
+        assert_eq!(aead.decrypt_in_place(&NONCE, b"aad", &mut buffer), Err(Error::AuthenticationFailed));
+        assert_eq!(buffer, sealed);
+
+        let mut detached = b"detached".to_vec();
+        let tag = aead.encrypt_in_place_detached(&NONCE, b"aad", &mut detached).unwrap();
+        let ciphertext = detached.clone();
+        let mut bad_tag = tag;
+        bad_tag[0] ^= 1;
+        assert_eq!(aead.decrypt_in_place_detached(&NONCE, b"aad", &mut detached, &bad_tag), Err(Error::AuthenticationFailed));
+        assert_eq!(detached, ciphertext);
+        aead.decrypt_in_place_detached(&NONCE, b"aad", &mut detached, &tag).unwrap();
+        assert_eq!(detached, b"detached");
+    }
+
+    #[test]
+    fn detached_matches_attached() {
+        let aead = aead();
+        let mut buffer = PLAINTEXT.to_vec();
+        let tag = aead.encrypt_in_place_detached(&NONCE, &hex_to_bytes(AAD), &mut buffer).unwrap();
+        assert_eq!(buffer, hex_to_bytes(CIPHERTEXT));
+        assert_eq!(tag.to_vec(), hex_to_bytes(TAG));
+    }
+
+    // Feeds `data` to `update` in pieces of the given lengths, cycling.
+    fn split_update(data: &mut [u8], lengths: &[usize], update: &mut dyn FnMut(&mut [u8]) -> Result<()>) {
+        let mut position = 0;
+        let mut i = 0;
+        while position < data.len() {
+            let end = core::cmp::min(position + lengths[i % lengths.len()], data.len());
+            update(&mut data[position..end]).unwrap();
+            position = end;
+            i += 1;
+        }
+    }
+
+    #[test]
+    fn streaming_matches_one_shot() {
+        let aead = aead();
+        let aad = hex_to_bytes(AAD);
+        let splits: [&[usize]; 5] = [&[1], &[3, 7], &[15, 17, 63], &[64, 1], &[200]];
+        for lengths in splits.iter() {
+            let mut encryptor = aead.begin_encrypt(&NONCE).unwrap();
+            encryptor.update_aad(&aad[..5]).unwrap();
+            encryptor.update_aad(&aad[5..]).unwrap();
+            let mut buffer = PLAINTEXT.to_vec();
+            split_update(&mut buffer, lengths, &mut |piece| encryptor.update(piece));
+            assert_eq!(buffer, hex_to_bytes(CIPHERTEXT));
+            assert_eq!(encryptor.finalize().to_vec(), hex_to_bytes(TAG));
+
+            let mut decryptor = aead.begin_decrypt(&NONCE).unwrap();
+            decryptor.update_aad(&aad).unwrap();
+            split_update(&mut buffer, lengths, &mut |piece| decryptor.update(piece));
+            assert_eq!(buffer, PLAINTEXT);
+            let tag: Tag = hex_to_bytes(TAG).as_slice().try_into().unwrap();
+            assert_eq!(decryptor.finalize_verify(&tag), Ok(()));
+        }
+    }
+
+    #[test]
+    fn streaming_empty_message() {
+        let aead = aead();
+        let mut encryptor = aead.begin_encrypt(&NONCE).unwrap();
+        encryptor.update_aad(b"only aad").unwrap();
+        let tag = encryptor.finalize();
+        let (ciphertext, expected) = aead.encrypt(&NONCE, b"only aad", b"").unwrap();
+        assert!(ciphertext.is_empty());
+        assert_eq!(tag, expected);
+    }
+
+    #[test]
+    fn aad_after_message() {
+        let aead = aead();
+        let mut encryptor = aead.begin_encrypt(&NONCE).unwrap();
+        encryptor.update_aad(b"aad").unwrap();
+        encryptor.update(&mut [0; 4]).unwrap();
+        assert_eq!(encryptor.update_aad(b"late"), Err(Error::AadAfterMessage));
+
+        // An empty update still closes the AAD.
+        let mut decryptor = aead.begin_decrypt(&NONCE).unwrap();
+        decryptor.update(&mut []).unwrap();
+        assert_eq!(decryptor.update_aad(b"late"), Err(Error::AadAfterMessage));
+    }
+
+    #[test]
+    fn streaming_rejects_bad_tags() {
+        let aead = aead();
+        let (mut ciphertext, tag) = aead.encrypt(&NONCE, b"aad", b"streamed message").unwrap();
+        let mut decryptor = aead.begin_decrypt(&NONCE).unwrap();
+        decryptor.update_aad(b"aad").unwrap();
+        ciphertext[3] ^= 1;
+        decryptor.update(&mut ciphertext).unwrap();
+        assert_eq!(decryptor.finalize_verify(&tag), Err(Error::AuthenticationFailed));
+    }
+}
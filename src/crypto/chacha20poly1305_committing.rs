@@ -0,0 +1,124 @@
+use crate::crypto::chacha20poly1305::{Chacha20Poly1305, Tag};
+use crate::crypto::utils;
+use crate::error::{Error, Result};
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+pub const COMMITMENT_LENGTH: usize = 32;
+
+/// ChaCha20-Poly1305 with a key commitment prepended to the ciphertext.
+///
+/// Poly1305 is not key-committing, so an attacker can craft one ciphertext
+/// that opens under several keys. The commitment is the second half of
+/// ChaCha20 block 0 for the key and nonce, the half RFC 8439 discards after
+/// taking the Poly1305 key from the first. Decryption recomputes it and fails
+/// before touching the tag if the key does not match.
+pub struct Chacha20Poly1305Committing {
+    aead: Chacha20Poly1305,
+}
+
+impl Chacha20Poly1305Committing {
+    /// Returns the commitment followed by the ciphertext, and the tag.
+    pub fn encrypt(&self, nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, Tag)> {
+        let mut ciphertext: Vec<u8> = Vec::new();
+        ciphertext.extend_from_slice(&self.commitment(nonce)?);
+        ciphertext.extend_from_slice(plaintext);
+        let tag = self.aead.encrypt_in_place_detached(nonce, aad, &mut ciphertext[COMMITMENT_LENGTH..])?;
+        Ok((ciphertext, tag))
+    }
+
+    pub fn decrypt(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+        let tag: Tag = tag.try_into().map_err(|_| Error::InvalidTagLength)?;
+        if ciphertext.len() < COMMITMENT_LENGTH {
+            return Err(Error::AuthenticationFailed);
+        }
+        let (commitment, ciphertext) = ciphertext.split_at(COMMITMENT_LENGTH);
+        if !utils::constant_time_eq(commitment, &self.commitment(nonce)?) {
+            return Err(Error::AuthenticationFailed);
+        }
+        let mut plaintext = ciphertext.to_vec();
+        self.aead.decrypt_in_place_detached(nonce, aad, &mut plaintext, &tag)?;
+        Ok(plaintext)
+    }
+
+    pub fn commitment(&self, nonce: &[u8; 12]) -> Result<[u8; COMMITMENT_LENGTH]> {
+        let first_block = self.aead.first_block(nonce)?;
+        let mut commitment: [u8; COMMITMENT_LENGTH] = [0; COMMITMENT_LENGTH];
+        commitment.copy_from_slice(&first_block[32..64]);
+        return Ok(commitment);
+    }
+
+    pub fn new_from_slice(key: &[u8]) -> Result<Chacha20Poly1305Committing> {
+        let key: [u8; 32] = key.try_into().map_err(|_| Error::InvalidKeyLength)?;
+        Ok(Chacha20Poly1305Committing::new(key))
+    }
+
+    pub fn new(key: [u8; 32]) -> Chacha20Poly1305Committing {
+        Chacha20Poly1305Committing {
+            aead: Chacha20Poly1305::new(key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::chacha20::ChaCha20;
+
+    const KEY: [u8; 32] = [0x61; 32];
+    const NONCE: [u8; 12] = [3; 12];
+
+    #[test]
+    fn round_trip() {
+        let aead = Chacha20Poly1305Committing::new(KEY);
+        let (ciphertext, tag) = aead.encrypt(&NONCE, b"aad", b"committed").unwrap();
+        assert_eq!(ciphertext.len(), COMMITMENT_LENGTH + 9);
+        assert_eq!(ciphertext[..COMMITMENT_LENGTH].to_vec(), aead.commitment(&NONCE).unwrap().to_vec());
+        assert_eq!(aead.decrypt(&NONCE, b"aad", &ciphertext, &tag).unwrap(), b"committed");
+
+        // The body is ordinary ChaCha20-Poly1305.
+        let (plain_ciphertext, plain_tag) = Chacha20Poly1305::new(KEY).encrypt(&NONCE, b"aad", b"committed").unwrap();
+        assert_eq!(ciphertext[COMMITMENT_LENGTH..].to_vec(), plain_ciphertext);
+        assert_eq!(tag, plain_tag);
+    }
+
+    #[test]
+    fn commitment_is_the_discarded_half_of_block_0() {
+        let block = ChaCha20::new(KEY, NONCE).chacha_block().unwrap();
+        assert_eq!(Chacha20Poly1305Committing::new(KEY).commitment(&NONCE).unwrap().to_vec(), block[32..].to_vec());
+    }
+
+    #[test]
+    fn commitment_depends_on_key_and_nonce() {
+        let commitment = Chacha20Poly1305Committing::new(KEY).commitment(&NONCE).unwrap();
+        assert_ne!(Chacha20Poly1305Committing::new([0x62; 32]).commitment(&NONCE).unwrap(), commitment);
+        assert_ne!(Chacha20Poly1305Committing::new(KEY).commitment(&[4; 12]).unwrap(), commitment);
+    }
+
+    #[test]
+    fn wrong_key_fails_on_the_commitment() {
+        let (ciphertext, tag) = Chacha20Poly1305Committing::new(KEY).encrypt(&NONCE, b"", b"secret").unwrap();
+        let other = Chacha20Poly1305Committing::new([0x62; 32]);
+        assert_eq!(other.decrypt(&NONCE, b"", &ciphertext, &tag), Err(Error::AuthenticationFailed));
+
+        // With the commitment swapped for the other key's, the commitment
+        // check passes and the tag check still fails.
+        let mut recommitted = ciphertext.clone();
+        recommitted[..COMMITMENT_LENGTH].copy_from_slice(&other.commitment(&NONCE).unwrap());
+        assert_eq!(other.decrypt(&NONCE, b"", &recommitted, &tag), Err(Error::AuthenticationFailed));
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let aead = Chacha20Poly1305Committing::new(KEY);
+        let (ciphertext, tag) = aead.encrypt(&NONCE, b"aad", b"committed").unwrap();
+        for index in [0, COMMITMENT_LENGTH - 1, COMMITMENT_LENGTH, ciphertext.len() - 1].iter() {
+            let mut tampered = ciphertext.clone();
+            tampered[*index] ^= 1;
+            assert_eq!(aead.decrypt(&NONCE, b"aad", &tampered, &tag), Err(Error::AuthenticationFailed));
+        }
+        assert_eq!(aead.decrypt(&NONCE, b"aad", &ciphertext[..COMMITMENT_LENGTH - 1], &tag), Err(Error::AuthenticationFailed));
+        assert_eq!(aead.decrypt(&NONCE, b"aad", &ciphertext, &tag[..8]), Err(Error::InvalidTagLength));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::crypto::poly1305::{Poly1305, Tag};
+use crate::crypto::chacha20::{poly1305_key_gen_legacy, ChaCha20};
+use crate::crypto::utils;
+use crate::error::{Error, Result};
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+/// The original ChaCha20-Poly1305 AEAD (draft-agl-tls-chacha20poly1305,
+/// libsodium's `crypto_aead_chacha20poly1305`), for peers that predate
+/// RFC 8439.
+///
+/// It uses the legacy ChaCha20 layout with a 64-bit nonce and counter, and
+/// MACs `aad || le64(aad_len) || ciphertext || le64(ciphertext_len)` with no
+/// padding between the fields.
+pub struct Chacha20Poly1305Legacy {
+    key: [u8; 32],
+}
+
+impl Chacha20Poly1305Legacy {
+    pub fn encrypt(&self, nonce: &[u8; 8], aad: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, Tag)> {
+        let mut ciphertext = plaintext.to_vec();
+        let tag = self.encrypt_in_place_detached(nonce, aad, &mut ciphertext)?;
+        Ok((ciphertext, tag))
+    }
+
+    pub fn decrypt(&self, nonce: &[u8; 8], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+        let tag: Tag = tag.try_into().map_err(|_| Error::InvalidTagLength)?;
+        let mut plaintext = ciphertext.to_vec();
+        self.decrypt_in_place_detached(nonce, aad, &mut plaintext, &tag)?;
+        Ok(plaintext)
+    }
+
+    pub fn encrypt_in_place_detached(&self, nonce: &[u8; 8], aad: &[u8], buffer: &mut [u8]) -> Result<Tag> {
+        let mac_key = poly1305_key_gen_legacy(self.key, *nonce);
+        ChaCha20::new_legacy_with_counter(self.key, *nonce, 1).encrypt_in_place(buffer)?;
+        Ok(Self::compute_tag(mac_key, aad, buffer))
+    }
+
+    pub fn decrypt_in_place_detached(&self, nonce: &[u8; 8], aad: &[u8], buffer: &mut [u8], tag: &Tag) -> Result<()> {
+        let mac_key = poly1305_key_gen_legacy(self.key, *nonce);
+        let tag_2 = Self::compute_tag(mac_key, aad, buffer);
+        if !utils::constant_time_eq(tag, &tag_2) {
+            return Err(Error::AuthenticationFailed);
+        }
+        ChaCha20::new_legacy_with_counter(self.key, *nonce, 1).encrypt_in_place(buffer)
+    }
+
+    fn compute_tag(mac_key: [u8; 32], aad: &[u8], ciphertext: &[u8]) -> Tag {
+        let mut poly1305 = Poly1305::new(mac_key);
+        poly1305.update(aad);
+        poly1305.update(&(aad.len() as u64).to_le_bytes());
+        poly1305.update(ciphertext);
+        poly1305.update(&(ciphertext.len() as u64).to_le_bytes());
+        poly1305.finalize()
+    }
+
+    pub fn new_from_slice(key: &[u8]) -> Result<Chacha20Poly1305Legacy> {
+        let key: [u8; 32] = key.try_into().map_err(|_| Error::InvalidKeyLength)?;
+        Ok(Chacha20Poly1305Legacy::new(key))
+    }
+
+    pub fn new(key: [u8; 32]) -> Chacha20Poly1305Legacy {
+        Chacha20Poly1305Legacy {
+            key: key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+
+    // draft-agl-tls-chacha20poly1305-04, section 7, which libsodium's
+    // crypto_aead_chacha20poly1305 also reproduces.
+    #[test]
+    fn draft_agl_test_vector() {
+        let key: [u8; 32] = hex_to_bytes("4290bcb154173531f314af57f3be3b5006da371ece272afa1b5dbdd1100a1007").try_into().unwrap();
+        let nonce: [u8; 8] = hex_to_bytes("cd7cf67be39c794a").try_into().unwrap();
+        let aad = hex_to_bytes("87e229d4500845a079c0");
+        let plaintext = hex_to_bytes("86d09974840bded2a5ca");
+
+        let aead = Chacha20Poly1305Legacy::new(key);
+        let (ciphertext, tag) = aead.encrypt(&nonce, &aad, &plaintext).unwrap();
+        assert_eq!(ciphertext, hex_to_bytes("e3e446f7ede9a19b62a4"));
+        assert_eq!(tag.to_vec(), hex_to_bytes("677dabf4e3d24b876bb284753896e1d6"));
+        assert_eq!(aead.decrypt(&nonce, &aad, &ciphertext, &tag).unwrap(), plaintext);
+
+        let mut tampered = ciphertext.clone();
+        tampered[0] ^= 1;
+        assert_eq!(aead.decrypt(&nonce, &aad, &tampered, &tag), Err(Error::AuthenticationFailed));
+        assert_eq!(aead.decrypt(&nonce, b"", &ciphertext, &tag), Err(Error::AuthenticationFailed));
+    }
+
+    #[test]
+    fn empty_message() {
+        let aead = Chacha20Poly1305Legacy::new([7; 32]);
+        let (ciphertext, tag) = aead.encrypt(&[1; 8], b"header", b"").unwrap();
+        assert!(ciphertext.is_empty());
+        assert_eq!(aead.decrypt(&[1; 8], b"header", &ciphertext, &tag).unwrap(), b"");
+        assert_eq!(aead.decrypt(&[1; 8], b"header", &ciphertext, &tag[..15]), Err(Error::InvalidTagLength));
+    }
+}
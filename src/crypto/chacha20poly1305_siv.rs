@@ -0,0 +1,209 @@
+use crate::crypto::chacha20::{hchacha20, ChaCha20};
+use crate::crypto::chacha20poly1305::{Chacha20Poly1305, Tag};
+use crate::crypto::utils;
+use crate::error::{Error, Result};
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+/// A deterministic, nonce-misuse-resistant AEAD built from ChaCha20 and
+/// Poly1305 in the synthetic IV style of AES-GCM-SIV.
+///
+/// For each nonce, the first two ChaCha20 blocks under a subkey give a
+/// Poly1305 key, a PRF key and an encryption key. The subkey is HChaCha20 of
+/// the master key over a fixed label, so these blocks never coincide with the
+/// one-time Poly1305 keys or keystream `Chacha20Poly1305` derives from the
+/// same key and nonce. The synthetic IV is
+/// HChaCha20, keyed with the PRF key, applied to the Poly1305 hash of the AAD
+/// and *plaintext*; it is used as the tag and, through HChaCha20 under the
+/// encryption key, selects the ChaCha20 key that encrypts the message.
+///
+/// Because the IV depends on the whole message, repeating a nonce only
+/// reveals whether two (AAD, plaintext) pairs were identical. Nonces should
+/// still be unique where possible.
+pub struct Chacha20Poly1305Siv {
+    subkey: [u8; 32],
+}
+
+struct SivKeys {
+    mac_key: [u8; 32],
+    prf_key: [u8; 32],
+    encryption_key: [u8; 32],
+}
+
+impl Chacha20Poly1305Siv {
+    // The message keystream starts at block 0, giving 2^32 blocks.
+    const MAX_MESSAGE_LENGTH: u64 = (u32::MAX as u64 + 1) * 64;
+    const SUBKEY_LABEL: [u8; 16] = *b"chacha20-siv-key";
+
+    pub fn encrypt(&self, nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, Tag)> {
+        let mut ciphertext = plaintext.to_vec();
+        let tag = self.encrypt_in_place_detached(nonce, aad, &mut ciphertext)?;
+        Ok((ciphertext, tag))
+    }
+
+    pub fn decrypt(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+        let tag: Tag = tag.try_into().map_err(|_| Error::InvalidTagLength)?;
+        let mut plaintext = ciphertext.to_vec();
+        self.decrypt_in_place_detached(nonce, aad, &mut plaintext, &tag)?;
+        Ok(plaintext)
+    }
+
+    pub fn encrypt_in_place_detached(&self, nonce: &[u8; 12], aad: &[u8], buffer: &mut [u8]) -> Result<Tag> {
+        Self::check_length(buffer.len())?;
+        let keys = self.derive_keys(nonce)?;
+        let tag = Self::synthetic_iv(&keys, aad, buffer);
+        Self::apply_keystream(&keys, nonce, &tag, buffer)?;
+        Ok(tag)
+    }
+
+    /// Decrypts `buffer` in place and checks the synthetic IV. The buffer is
+    /// restored to the ciphertext if verification fails.
+    pub fn decrypt_in_place_detached(&self, nonce: &[u8; 12], aad: &[u8], buffer: &mut [u8], tag: &Tag) -> Result<()> {
+        Self::check_length(buffer.len())?;
+        let keys = self.derive_keys(nonce)?;
+        Self::apply_keystream(&keys, nonce, tag, buffer)?;
+        let tag_2 = Self::synthetic_iv(&keys, aad, buffer);
+        if !utils::constant_time_eq(tag, &tag_2) {
+            Self::apply_keystream(&keys, nonce, tag, buffer)?;
+            return Err(Error::AuthenticationFailed);
+        }
+        Ok(())
+    }
+
+    fn check_length(length: usize) -> Result<()> {
+        if length as u64 > Self::MAX_MESSAGE_LENGTH {
+            return Err(Error::MessageTooLong);
+        }
+        Ok(())
+    }
+
+    fn derive_keys(&self, nonce: &[u8; 12]) -> Result<SivKeys> {
+        let mut cipher = ChaCha20::new(self.subkey, *nonce);
+        let first_block = cipher.chacha_block()?;
+        let second_block = cipher.chacha_block()?;
+        let mut keys = SivKeys {
+            mac_key: [0; 32],
+            prf_key: [0; 32],
+            encryption_key: [0; 32],
+        };
+        keys.mac_key.copy_from_slice(&first_block[0..32]);
+        keys.prf_key.copy_from_slice(&first_block[32..64]);
+        keys.encryption_key.copy_from_slice(&second_block[0..32]);
+        Ok(keys)
+    }
+
+    fn synthetic_iv(keys: &SivKeys, aad: &[u8], plaintext: &[u8]) -> Tag {
+        let hash = Chacha20Poly1305::compute_tag(keys.mac_key, aad, plaintext);
+        let mut tag: Tag = [0; 16];
+        tag.copy_from_slice(&hchacha20(keys.prf_key, hash)[0..16]);
+        return tag;
+    }
+
+    fn apply_keystream(keys: &SivKeys, nonce: &[u8; 12], tag: &Tag, buffer: &mut [u8]) -> Result<()> {
+        let message_key = hchacha20(keys.encryption_key, *tag);
+        ChaCha20::new(message_key, *nonce).encrypt_in_place(buffer)
+    }
+
+    pub fn new_from_slice(key: &[u8]) -> Result<Chacha20Poly1305Siv> {
+        let key: [u8; 32] = key.try_into().map_err(|_| Error::InvalidKeyLength)?;
+        Ok(Chacha20Poly1305Siv::new(key))
+    }
+
+    pub fn new(key: [u8; 32]) -> Chacha20Poly1305Siv {
+        Chacha20Poly1305Siv {
+            subkey: hchacha20(key, Self::SUBKEY_LABEL),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::chacha20::poly1305_key_gen;
+
+    const KEY: [u8; 32] = [0x24; 32];
+    const NONCE: [u8; 12] = [9; 12];
+
+    #[test]
+    fn round_trip() {
+        let siv = Chacha20Poly1305Siv::new(KEY);
+        for length in [0, 1, 16, 64, 65, 300].iter() {
+            let plaintext = vec![0xa7; *length];
+            let (ciphertext, tag) = siv.encrypt(&NONCE, b"aad", &plaintext).unwrap();
+            assert_eq!(ciphertext.len(), *length);
+            assert_eq!(siv.decrypt(&NONCE, b"aad", &ciphertext, &tag).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let first = Chacha20Poly1305Siv::new(KEY).encrypt(&NONCE, b"aad", b"message").unwrap();
+        let second = Chacha20Poly1305Siv::new(KEY).encrypt(&NONCE, b"aad", b"message").unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn tag_depends_on_every_input() {
+        let siv = Chacha20Poly1305Siv::new(KEY);
+        let (ciphertext, tag) = siv.encrypt(&NONCE, b"aad", b"message").unwrap();
+        let others = [
+            siv.encrypt(&[8; 12], b"aad", b"message").unwrap(),
+            siv.encrypt(&NONCE, b"aaD", b"message").unwrap(),
+            siv.encrypt(&NONCE, b"aad", b"messagE").unwrap(),
+            Chacha20Poly1305Siv::new([0x25; 32]).encrypt(&NONCE, b"aad", b"message").unwrap(),
+        ];
+        for (other_ciphertext, other_tag) in others.iter() {
+            assert_ne!(*other_tag, tag);
+            assert_ne!(*other_ciphertext, ciphertext);
+        }
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let siv = Chacha20Poly1305Siv::new(KEY);
+        let (ciphertext, tag) = siv.encrypt(&NONCE, b"aad", b"message").unwrap();
+        let mut bad_ciphertext = ciphertext.clone();
+        bad_ciphertext[0] ^= 1;
+        let mut bad_tag = tag;
+        bad_tag[15] ^= 1;
+        assert_eq!(siv.decrypt(&NONCE, b"aad", &bad_ciphertext, &tag), Err(Error::AuthenticationFailed));
+        assert_eq!(siv.decrypt(&NONCE, b"aad", &ciphertext, &bad_tag), Err(Error::AuthenticationFailed));
+        assert_eq!(siv.decrypt(&NONCE, b"", &ciphertext, &tag), Err(Error::AuthenticationFailed));
+        assert_eq!(siv.decrypt(&[8; 12], b"aad", &ciphertext, &tag), Err(Error::AuthenticationFailed));
+        assert_eq!(siv.decrypt(&NONCE, b"aad", &ciphertext, &tag[..15]), Err(Error::InvalidTagLength));
+    }
+
+    #[test]
+    fn failed_decryption_restores_the_buffer() {
+        let siv = Chacha20Poly1305Siv::new(KEY);
+        let mut buffer = b"restore me".to_vec();
+        let tag = siv.encrypt_in_place_detached(&NONCE, b"aad", &mut buffer).unwrap();
+        buffer[2] ^= 1;
+        let tampered = buffer.clone();
+        assert_eq!(siv.decrypt_in_place_detached(&NONCE, b"aad", &mut buffer, &tag), Err(Error::AuthenticationFailed));
+        assert_eq!(buffer, tampered);
+        buffer[2] ^= 1;
+        siv.decrypt_in_place_detached(&NONCE, b"aad", &mut buffer, &tag).unwrap();
+        assert_eq!(buffer, b"restore me");
+    }
+
+    // None of the SIV subkeys may equal the RFC 8439 Poly1305 key or a block
+    // of the RFC 8439 keystream for the same key and nonce.
+    #[test]
+    fn keys_are_separate_from_the_plain_aead() {
+        let siv = Chacha20Poly1305Siv::new(KEY);
+        let keys = siv.derive_keys(&NONCE).unwrap();
+        let mut plain = ChaCha20::new(KEY, NONCE);
+        let mut plain_blocks: Vec<u8> = Vec::new();
+        for _ in 0..4 {
+            plain_blocks.extend_from_slice(&plain.chacha_block().unwrap());
+        }
+        assert_eq!(plain_blocks[..32].to_vec(), poly1305_key_gen(KEY, NONCE).to_vec());
+        for subkey in [keys.mac_key, keys.prf_key, keys.encryption_key].iter() {
+            for block in plain_blocks.chunks(32) {
+                assert_ne!(block, &subkey[..]);
+            }
+        }
+    }
+}
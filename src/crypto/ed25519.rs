@@ -0,0 +1,371 @@
+use crate::crypto::utils::field25519::FieldElement;
+use crate::crypto::utils::u256::U256;
+use crate::error::{Error, Result};
+use crate::hashes::sha2::SHA512;
+
+use core::convert::TryInto;
+
+pub const SIGNATURE_LENGTH: usize = 64;
+
+// The order L of the prime-order subgroup, 2^252 + 27742317777372353535851937790883648493.
+fn group_order() -> U256 {
+    U256::from_limbs([0x5812631a5cf5d3ed, 0x14def9dea2f79cd6, 0x0000000000000000, 0x1000000000000000])
+}
+
+// Reduces the 512-bit value `high * 2^256 + low` mod L one bit at a time, with
+// a constant-time conditional subtraction at each step.
+fn reduce_scalar(low: U256, high: U256) -> U256 {
+    let low_bytes = low.to_le_bytes();
+    let high_bytes = high.to_le_bytes();
+    let mut remainder = U256::zero();
+    for i in (0..512).rev() {
+        let bit = if i >= 256 {
+            (high_bytes[(i - 256) / 8] >> (i % 8)) & 1
+        } else {
+            (low_bytes[i / 8] >> (i % 8)) & 1
+        };
+        let (doubled, _) = remainder.overflowing_add(remainder);
+        let (shifted, _) = doubled.overflowing_add(U256::from(bit));
+        let (difference, borrow) = shifted.overflowing_sub(group_order());
+        remainder = U256::conditional_select(difference, shifted, borrow as u8);
+    }
+    return remainder;
+}
+
+fn scalar_from_hash(hash: [u8; 64]) -> U256 {
+    let mut low: [u8; 32] = [0; 32];
+    let mut high: [u8; 32] = [0; 32];
+    low.copy_from_slice(&hash[0..32]);
+    high.copy_from_slice(&hash[32..64]);
+    reduce_scalar(U256::from_le_bytes(low), U256::from_le_bytes(high))
+}
+
+// (a * b + c) mod L.
+fn scalar_mul_add(a: U256, b: U256, c: U256) -> U256 {
+    let (low, high) = a.widening_mul(b);
+    let (low, carry) = low.overflowing_add(c);
+    let (high, _) = high.overflowing_add(U256::from(carry as u8));
+    reduce_scalar(low, high)
+}
+
+fn hash_to_scalar(parts: &[&[u8]]) -> U256 {
+    let mut hasher = SHA512::new();
+    for part in parts.iter() {
+        hasher.update(part);
+    }
+    scalar_from_hash(hasher.digest())
+}
+
+/// A point on edwards25519 in extended coordinates (X : Y : Z : T) with
+/// x = X/Z, y = Y/Z and xy = T/Z (RFC 8032 section 5.1.4).
+#[derive(Copy, Clone)]
+struct EdwardsPoint {
+    x: FieldElement,
+    y: FieldElement,
+    z: FieldElement,
+    t: FieldElement,
+}
+
+impl EdwardsPoint {
+    const BASE_POINT: [u8; 32] = [
+        0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+        0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+        0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+        0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66
+    ];
+
+    fn d() -> FieldElement {
+        FieldElement::from_limbs([0x75eb4dca135978a3, 0x00700a4d4141d8ab, 0x8cc740797779e898, 0x52036cee2b6ffe73])
+    }
+
+    fn d2() -> FieldElement {
+        FieldElement::from_limbs([0xebd69b9426b2f159, 0x00e0149a8283b156, 0x198e80f2eef3d130, 0x2406d9dc56dffce7])
+    }
+
+    fn sqrt_minus_one() -> FieldElement {
+        FieldElement::from_limbs([0xc4ee1b274a0ea0b0, 0x2f431806ad2fe478, 0x2b4d00993dfbd7a7, 0x2b8324804fc1df0b])
+    }
+
+    fn identity() -> EdwardsPoint {
+        EdwardsPoint {
+            x: FieldElement::zero(),
+            y: FieldElement::one(),
+            z: FieldElement::one(),
+            t: FieldElement::zero(),
+        }
+    }
+
+    fn base_point() -> EdwardsPoint {
+        match EdwardsPoint::decode(&Self::BASE_POINT) {
+            Ok(point) => point,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    fn add(self, other: EdwardsPoint) -> EdwardsPoint {
+        let a = (self.y - self.x) * (other.y - other.x);
+        let b = (self.y + self.x) * (other.y + other.x);
+        let c = self.t * Self::d2() * other.t;
+        let d = self.z * (other.z + other.z);
+        let e = b - a;
+        let f = d - c;
+        let g = d + c;
+        let h = b + a;
+        EdwardsPoint {
+            x: e * f,
+            y: g * h,
+            z: f * g,
+            t: e * h,
+        }
+    }
+
+    fn double(self) -> EdwardsPoint {
+        let a = self.x.square();
+        let b = self.y.square();
+        let c = self.z.square() + self.z.square();
+        let h = a + b;
+        let e = h - (self.x + self.y).square();
+        let g = a - b;
+        let f = c + g;
+        EdwardsPoint {
+            x: e * f,
+            y: g * h,
+            z: f * g,
+            t: e * h,
+        }
+    }
+
+    fn conditional_select(first: EdwardsPoint, second: EdwardsPoint, choice: u8) -> EdwardsPoint {
+        EdwardsPoint {
+            x: FieldElement::conditional_select(first.x, second.x, choice),
+            y: FieldElement::conditional_select(first.y, second.y, choice),
+            z: FieldElement::conditional_select(first.z, second.z, choice),
+            t: FieldElement::conditional_select(first.t, second.t, choice),
+        }
+    }
+
+    // Double-and-always-add over all 256 bits, so the work done does not
+    // depend on the scalar.
+    fn scalar_mul(self, scalar: U256) -> EdwardsPoint {
+        let scalar_bytes = scalar.to_le_bytes();
+        let mut result = EdwardsPoint::identity();
+        for i in (0..256).rev() {
+            result = result.double();
+            let sum = result.add(self);
+            result = EdwardsPoint::conditional_select(result, sum, (scalar_bytes[i / 8] >> (i % 8)) & 1);
+        }
+        return result;
+    }
+
+    fn mul_by_cofactor(self) -> EdwardsPoint {
+        self.double().double().double()
+    }
+
+    fn encode(self) -> [u8; 32] {
+        let z_inverse = self.z.invert();
+        let x = self.x * z_inverse;
+        let y = self.y * z_inverse;
+        let mut encoded = y.to_bytes();
+        encoded[31] |= (x.is_negative() as u8) << 7;
+        return encoded;
+    }
+
+    // Point decoding from RFC 8032 section 5.1.3. Only ever used on public
+    // values, so it may branch.
+    fn decode(encoded: &[u8; 32]) -> Result<EdwardsPoint> {
+        let x_sign = encoded[31] >> 7;
+        let y = FieldElement::from_bytes(*encoded);
+        let mut y_bytes = *encoded;
+        y_bytes[31] &= 0x7F;
+        if y.to_bytes() != y_bytes {
+            return Err(Error::InvalidPublicKey);
+        }
+        let u = y.square() - FieldElement::one();
+        let v = Self::d() * y.square() + FieldElement::one();
+        let v3 = v.square() * v;
+        let v7 = v3.square() * v;
+        let exponent = U256::from_limbs([0xFFFFFFFFFFFFFFFD, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0x0FFFFFFFFFFFFFFF]);
+        let mut x = u * v3 * (u * v7).pow(exponent);
+        let v_x2 = v * x.square();
+        if v_x2 == -u {
+            x = x * Self::sqrt_minus_one();
+        } else if v_x2 != u {
+            return Err(Error::InvalidPublicKey);
+        }
+        if x == FieldElement::zero() && x_sign == 1 {
+            return Err(Error::InvalidPublicKey);
+        }
+        if x.is_negative() as u8 != x_sign {
+            x = -x;
+        }
+        Ok(EdwardsPoint {
+            x: x,
+            y: y,
+            z: FieldElement::one(),
+            t: x * y,
+        })
+    }
+}
+
+/// Checks an Ed25519 signature (RFC 8032 section 5.1.7), using the cofactored
+/// equation [8][S]B = [8]R + [8][k]A and rejecting non-canonical S.
+pub fn verify(public_key: &[u8; 32], message: &[u8], signature: &[u8]) -> Result<()> {
+    if signature.len() != SIGNATURE_LENGTH {
+        return Err(Error::InvalidSignature);
+    }
+    let a = EdwardsPoint::decode(public_key)?;
+    let r_bytes: [u8; 32] = signature[0..32].try_into().map_err(|_| Error::InvalidSignature)?;
+    let s_bytes: [u8; 32] = signature[32..64].try_into().map_err(|_| Error::InvalidSignature)?;
+    let r = EdwardsPoint::decode(&r_bytes).map_err(|_| Error::InvalidSignature)?;
+    let s = U256::from_le_bytes(s_bytes);
+    if s >= group_order() {
+        return Err(Error::InvalidSignature);
+    }
+    let k = hash_to_scalar(&[&r_bytes, public_key, message]);
+    let lhs = EdwardsPoint::base_point().scalar_mul(s).mul_by_cofactor();
+    let rhs = r.add(a.scalar_mul(k)).mul_by_cofactor();
+    if lhs.encode() != rhs.encode() {
+        return Err(Error::InvalidSignature);
+    }
+    Ok(())
+}
+
+/// An Ed25519 signing key (RFC 8032 section 5.1).
+///
+/// The 32-byte secret is the RFC's private key and must come from a secure
+/// random source; everything else, including the public key, is derived from
+/// it. Signing is deterministic.
+pub struct Ed25519 {
+    scalar: U256,
+    prefix: [u8; 32],
+    public_key: [u8; 32],
+}
+
+impl Ed25519 {
+    pub fn public_key(&self) -> [u8; 32] {
+        self.public_key
+    }
+
+    pub fn sign(&self, message: &[u8]) -> [u8; SIGNATURE_LENGTH] {
+        let r = hash_to_scalar(&[&self.prefix, message]);
+        let r_bytes = EdwardsPoint::base_point().scalar_mul(r).encode();
+        let k = hash_to_scalar(&[&r_bytes, &self.public_key, message]);
+        let s = scalar_mul_add(k, reduce_scalar(self.scalar, U256::zero()), r);
+        let mut signature: [u8; SIGNATURE_LENGTH] = [0; SIGNATURE_LENGTH];
+        signature[0..32].copy_from_slice(&r_bytes);
+        signature[32..64].copy_from_slice(&s.to_le_bytes());
+        return signature;
+    }
+
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<()> {
+        verify(&self.public_key, message, signature)
+    }
+
+    pub fn new_from_slice(secret: &[u8]) -> Result<Ed25519> {
+        let secret: [u8; 32] = secret.try_into().map_err(|_| Error::InvalidKeyLength)?;
+        Ok(Ed25519::new(secret))
+    }
+
+    pub fn new(secret: [u8; 32]) -> Ed25519 {
+        let mut hasher = SHA512::new();
+        hasher.update(&secret);
+        let hash = hasher.digest();
+        let mut scalar_bytes: [u8; 32] = [0; 32];
+        let mut prefix: [u8; 32] = [0; 32];
+        scalar_bytes.copy_from_slice(&hash[0..32]);
+        prefix.copy_from_slice(&hash[32..64]);
+        scalar_bytes[0] &= 248;
+        scalar_bytes[31] &= 127;
+        scalar_bytes[31] |= 64;
+        let scalar = U256::from_le_bytes(scalar_bytes);
+        Ed25519 {
+            scalar: scalar,
+            prefix: prefix,
+            public_key: EdwardsPoint::base_point().scalar_mul(scalar).encode(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+
+    // RFC 8032 section 7.1, TEST 1 to TEST 3.
+    const TEST_VECTORS: [(&str, &str, &str, &str); 3] = [
+        (
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            "",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            "72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            "af82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    #[test]
+    fn rfc_8032_test_vectors() {
+        for (secret, public_key, message, signature) in TEST_VECTORS.iter() {
+            let signer = Ed25519::new_from_slice(&hex_to_bytes(secret)).unwrap();
+            let message = hex_to_bytes(message);
+            assert_eq!(signer.public_key().to_vec(), hex_to_bytes(public_key));
+            assert_eq!(signer.sign(&message).to_vec(), hex_to_bytes(signature));
+            assert!(verify(&signer.public_key(), &message, &hex_to_bytes(signature)).is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let (secret, _, message, signature) = TEST_VECTORS[2];
+        let signer = Ed25519::new_from_slice(&hex_to_bytes(secret)).unwrap();
+        let message = hex_to_bytes(message);
+        let signature = hex_to_bytes(signature);
+
+        // One byte of R, then one byte of S.
+        for index in [0, 40].iter() {
+            let mut tampered = signature.clone();
+            tampered[*index] ^= 1;
+            assert_eq!(signer.verify(&message, &tampered), Err(Error::InvalidSignature));
+        }
+        let mut tampered_message = message.clone();
+        tampered_message[0] ^= 1;
+        assert_eq!(signer.verify(&tampered_message, &signature), Err(Error::InvalidSignature));
+        assert_eq!(signer.verify(&message, &signature[..63]), Err(Error::InvalidSignature));
+    }
+
+    // S + L satisfies the verification equation just as S does, so only the
+    // range check stops this malleated signature.
+    #[test]
+    fn rejects_non_canonical_s() {
+        let (secret, _, message, signature) = TEST_VECTORS[0];
+        let signer = Ed25519::new_from_slice(&hex_to_bytes(secret)).unwrap();
+        let mut signature = hex_to_bytes(signature);
+        let s_bytes: [u8; 32] = signature[32..64].try_into().unwrap();
+        let (s_plus_l, _) = U256::from_le_bytes(s_bytes).overflowing_add(group_order());
+        signature[32..64].copy_from_slice(&s_plus_l.to_le_bytes());
+        assert_eq!(signer.verify(&hex_to_bytes(message), &signature), Err(Error::InvalidSignature));
+
+        signature[32..64].copy_from_slice(&group_order().to_le_bytes());
+        assert_eq!(signer.verify(&hex_to_bytes(message), &signature), Err(Error::InvalidSignature));
+    }
+
+    #[test]
+    fn rejects_non_canonical_points() {
+        // y = p encodes zero, but not canonically.
+        let mut encoded: [u8; 32] = [0xff; 32];
+        encoded[0] = 0xed;
+        encoded[31] = 0x7f;
+        assert!(EdwardsPoint::decode(&encoded).is_err());
+        assert!(verify(&encoded, b"", &[0; SIGNATURE_LENGTH]).is_err());
+    }
+}
@@ -0,0 +1,401 @@
+use crate::crypto::chacha20poly1305::{Chacha20Poly1305, Tag};
+use crate::crypto::nonce::{NonceSequence, XorNonce};
+use crate::crypto::x25519::X25519;
+use crate::error::{Error, Result};
+use crate::hashes::hkdf;
+use crate::hashes::sha2::SHA256;
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+/// DHKEM(X25519, HKDF-SHA256).
+pub const KEM_ID: u16 = 0x0020;
+/// HKDF-SHA256.
+pub const KDF_ID: u16 = 0x0001;
+/// ChaCha20Poly1305.
+pub const AEAD_ID: u16 = 0x0003;
+
+const MODE_BASE: u8 = 0x00;
+const MODE_PSK: u8 = 0x01;
+
+fn kem_suite_id() -> Vec<u8> {
+    let mut suite_id: Vec<u8> = b"KEM".to_vec();
+    suite_id.extend_from_slice(&KEM_ID.to_be_bytes());
+    return suite_id;
+}
+
+fn hpke_suite_id() -> Vec<u8> {
+    let mut suite_id: Vec<u8> = b"HPKE".to_vec();
+    suite_id.extend_from_slice(&KEM_ID.to_be_bytes());
+    suite_id.extend_from_slice(&KDF_ID.to_be_bytes());
+    suite_id.extend_from_slice(&AEAD_ID.to_be_bytes());
+    return suite_id;
+}
+
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> Vec<u8> {
+    let mut labeled_ikm: Vec<u8> = b"HPKE-v1".to_vec();
+    labeled_ikm.extend_from_slice(suite_id);
+    labeled_ikm.extend_from_slice(label);
+    labeled_ikm.extend_from_slice(ikm);
+    hkdf::extract::<SHA256>(salt, &labeled_ikm)
+}
+
+fn labeled_expand(suite_id: &[u8], prk: &[u8], label: &[u8], info: &[u8], length: usize) -> Result<Vec<u8>> {
+    if length > u16::MAX as usize {
+        return Err(Error::InvalidOutputLength);
+    }
+    let mut labeled_info: Vec<u8> = Vec::new();
+    labeled_info.extend_from_slice(&(length as u16).to_be_bytes());
+    labeled_info.extend_from_slice(b"HPKE-v1");
+    labeled_info.extend_from_slice(suite_id);
+    labeled_info.extend_from_slice(label);
+    labeled_info.extend_from_slice(info);
+    hkdf::expand::<SHA256>(prk, &labeled_info, length)
+}
+
+fn to_array(value: Vec<u8>) -> Result<[u8; 32]> {
+    value.as_slice().try_into().map_err(|_| Error::InvalidOutputLength)
+}
+
+/// DeriveKeyPair for DHKEM(X25519): turns at least 32 bytes of random input
+/// keying material into a `(secret_key, public_key)` pair.
+pub fn derive_key_pair(ikm: &[u8]) -> Result<([u8; 32], [u8; 32])> {
+    let suite_id = kem_suite_id();
+    let dkp_prk = labeled_extract(&suite_id, &[], b"dkp_prk", ikm);
+    let secret_key = to_array(labeled_expand(&suite_id, &dkp_prk, b"sk", &[], 32)?)?;
+    Ok((secret_key, X25519::new(secret_key).public_key()))
+}
+
+fn extract_and_expand(dh: &[u8], kem_context: &[u8]) -> Result<[u8; 32]> {
+    let suite_id = kem_suite_id();
+    let eae_prk = labeled_extract(&suite_id, &[], b"eae_prk", dh);
+    to_array(labeled_expand(&suite_id, &eae_prk, b"shared_secret", kem_context, 32)?)
+}
+
+// Returns `(shared_secret, enc)`.
+fn encap(recipient_public_key: &[u8; 32], ikm_e: &[u8]) -> Result<([u8; 32], [u8; 32])> {
+    let (ephemeral_secret, enc) = derive_key_pair(ikm_e)?;
+    let dh = X25519::new(ephemeral_secret).diffie_hellman(recipient_public_key)?;
+    let mut kem_context: Vec<u8> = enc.to_vec();
+    kem_context.extend_from_slice(recipient_public_key);
+    Ok((extract_and_expand(&dh, &kem_context)?, enc))
+}
+
+fn decap(enc: &[u8; 32], recipient_secret_key: &[u8; 32]) -> Result<[u8; 32]> {
+    let recipient = X25519::new(*recipient_secret_key);
+    let dh = recipient.diffie_hellman(enc)?;
+    let mut kem_context: Vec<u8> = enc.to_vec();
+    kem_context.extend_from_slice(&recipient.public_key());
+    extract_and_expand(&dh, &kem_context)
+}
+
+// Returns the AEAD key, base nonce and exporter secret.
+fn key_schedule(mode: u8, shared_secret: &[u8; 32], info: &[u8], psk: &[u8], psk_id: &[u8]) -> Result<([u8; 32], [u8; 12], [u8; 32])> {
+    if (mode == MODE_BASE) != (psk.is_empty() && psk_id.is_empty()) || psk.is_empty() != psk_id.is_empty() {
+        return Err(Error::InvalidPskInputs);
+    }
+    let suite_id = hpke_suite_id();
+    let mut key_schedule_context: Vec<u8> = vec![mode];
+    key_schedule_context.extend_from_slice(&labeled_extract(&suite_id, &[], b"psk_id_hash", psk_id));
+    key_schedule_context.extend_from_slice(&labeled_extract(&suite_id, &[], b"info_hash", info));
+    let secret = labeled_extract(&suite_id, shared_secret, b"secret", psk);
+    let key = to_array(labeled_expand(&suite_id, &secret, b"key", &key_schedule_context, 32)?)?;
+    let base_nonce: [u8; 12] = labeled_expand(&suite_id, &secret, b"base_nonce", &key_schedule_context, 12)?.as_slice().try_into().map_err(|_| Error::InvalidOutputLength)?;
+    let exporter_secret = to_array(labeled_expand(&suite_id, &secret, b"exp", &key_schedule_context, 32)?)?;
+    Ok((key, base_nonce, exporter_secret))
+}
+
+fn export(exporter_secret: &[u8; 32], exporter_context: &[u8], length: usize) -> Result<Vec<u8>> {
+    labeled_expand(&hpke_suite_id(), exporter_secret, b"sec", exporter_context, length)
+}
+
+/// The sender's encryption context. Message `i` is sealed under the base
+/// nonce XORed with `i`, so messages must be opened in the order sealed.
+pub struct SenderContext {
+    aead: Chacha20Poly1305,
+    nonces: XorNonce,
+    exporter_secret: [u8; 32],
+}
+
+impl SenderContext {
+    /// Returns `ciphertext || tag`.
+    pub fn seal(&mut self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.nonces.next_nonce()?;
+        let mut ciphertext = plaintext.to_vec();
+        let tag = self.aead.encrypt_in_place_detached(&nonce, aad, &mut ciphertext)?;
+        ciphertext.extend_from_slice(&tag);
+        Ok(ciphertext)
+    }
+
+    /// A secret derived from the context, bound to `exporter_context`.
+    pub fn export(&self, exporter_context: &[u8], length: usize) -> Result<Vec<u8>> {
+        export(&self.exporter_secret, exporter_context, length)
+    }
+}
+
+/// The recipient's decryption context. The sequence number only advances
+/// when a message opens successfully.
+pub struct ReceiverContext {
+    aead: Chacha20Poly1305,
+    nonces: XorNonce,
+    exporter_secret: [u8; 32],
+}
+
+impl ReceiverContext {
+    pub fn open(&mut self, aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        if ciphertext.len() < 16 {
+            return Err(Error::InvalidTagLength);
+        }
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - 16);
+        let tag: Tag = tag.try_into().map_err(|_| Error::InvalidTagLength)?;
+        let nonce = self.nonces.nonce_for(self.nonces.sequence_number());
+        let mut plaintext = ciphertext.to_vec();
+        self.aead.decrypt_in_place_detached(&nonce, aad, &mut plaintext, &tag)?;
+        self.nonces.next_nonce()?;
+        Ok(plaintext)
+    }
+
+    pub fn export(&self, exporter_context: &[u8], length: usize) -> Result<Vec<u8>> {
+        export(&self.exporter_secret, exporter_context, length)
+    }
+}
+
+fn setup_sender(mode: u8, recipient_public_key: &[u8; 32], info: &[u8], psk: &[u8], psk_id: &[u8], ikm_e: &[u8]) -> Result<([u8; 32], SenderContext)> {
+    let (shared_secret, enc) = encap(recipient_public_key, ikm_e)?;
+    let (key, base_nonce, exporter_secret) = key_schedule(mode, &shared_secret, info, psk, psk_id)?;
+    Ok((enc, SenderContext {
+        aead: Chacha20Poly1305::new(key),
+        nonces: XorNonce::new(base_nonce),
+        exporter_secret: exporter_secret,
+    }))
+}
+
+fn setup_receiver(mode: u8, enc: &[u8; 32], recipient_secret_key: &[u8; 32], info: &[u8], psk: &[u8], psk_id: &[u8]) -> Result<ReceiverContext> {
+    let shared_secret = decap(enc, recipient_secret_key)?;
+    let (key, base_nonce, exporter_secret) = key_schedule(mode, &shared_secret, info, psk, psk_id)?;
+    Ok(ReceiverContext {
+        aead: Chacha20Poly1305::new(key),
+        nonces: XorNonce::new(base_nonce),
+        exporter_secret: exporter_secret,
+    })
+}
+
+/// SetupBaseS: returns the encapsulated key `enc` to send alongside the
+/// ciphertexts and the sender context. `ikm_e` must be fresh random bytes
+/// (at least 32) for every call; the ephemeral key pair is derived from it.
+pub fn setup_base_sender(recipient_public_key: &[u8; 32], info: &[u8], ikm_e: &[u8]) -> Result<([u8; 32], SenderContext)> {
+    setup_sender(MODE_BASE, recipient_public_key, info, &[], &[], ikm_e)
+}
+
+/// SetupBaseR.
+pub fn setup_base_receiver(enc: &[u8; 32], recipient_secret_key: &[u8; 32], info: &[u8]) -> Result<ReceiverContext> {
+    setup_receiver(MODE_BASE, enc, recipient_secret_key, info, &[], &[])
+}
+
+/// SetupPSKS: like `setup_base_sender`, additionally authenticating the
+/// sender as a holder of the pre-shared key `psk` named by `psk_id`.
+pub fn setup_psk_sender(recipient_public_key: &[u8; 32], info: &[u8], psk: &[u8], psk_id: &[u8], ikm_e: &[u8]) -> Result<([u8; 32], SenderContext)> {
+    setup_sender(MODE_PSK, recipient_public_key, info, psk, psk_id, ikm_e)
+}
+
+/// SetupPSKR.
+pub fn setup_psk_receiver(enc: &[u8; 32], recipient_secret_key: &[u8; 32], info: &[u8], psk: &[u8], psk_id: &[u8]) -> Result<ReceiverContext> {
+    setup_receiver(MODE_PSK, enc, recipient_secret_key, info, psk, psk_id)
+}
+
+/// Single-shot base mode encryption, returning `(enc, ciphertext || tag)`.
+pub fn seal_base(recipient_public_key: &[u8; 32], info: &[u8], aad: &[u8], plaintext: &[u8], ikm_e: &[u8]) -> Result<([u8; 32], Vec<u8>)> {
+    let (enc, mut context) = setup_base_sender(recipient_public_key, info, ikm_e)?;
+    Ok((enc, context.seal(aad, plaintext)?))
+}
+
+pub fn open_base(enc: &[u8; 32], recipient_secret_key: &[u8; 32], info: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    setup_base_receiver(enc, recipient_secret_key, info)?.open(aad, ciphertext)
+}
+
+/// Single-shot PSK mode encryption, returning `(enc, ciphertext || tag)`.
+pub fn seal_psk(recipient_public_key: &[u8; 32], info: &[u8], aad: &[u8], plaintext: &[u8], psk: &[u8], psk_id: &[u8], ikm_e: &[u8]) -> Result<([u8; 32], Vec<u8>)> {
+    let (enc, mut context) = setup_psk_sender(recipient_public_key, info, psk, psk_id, ikm_e)?;
+    Ok((enc, context.seal(aad, plaintext)?))
+}
+
+pub fn open_psk(enc: &[u8; 32], recipient_secret_key: &[u8; 32], info: &[u8], aad: &[u8], ciphertext: &[u8], psk: &[u8], psk_id: &[u8]) -> Result<Vec<u8>> {
+    setup_psk_receiver(enc, recipient_secret_key, info, psk, psk_id)?.open(aad, ciphertext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+
+    struct Vector {
+        ikm_e: &'static str,
+        ikm_r: &'static str,
+        psk: &'static str,
+        psk_id: &'static str,
+        public_key_e: &'static str,
+        secret_key_e: &'static str,
+        public_key_r: &'static str,
+        secret_key_r: &'static str,
+        shared_secret: &'static str,
+        key: &'static str,
+        base_nonce: &'static str,
+        exporter_secret: &'static str,
+        // (sequence number, nonce, ciphertext) for the message below, with
+        // AAD "Count-" followed by the sequence number in decimal.
+        encryptions: [(u64, &'static str, &'static str); 6],
+        // (exporter context, 32-byte exported value).
+        exports: [(&'static str, &'static str); 3],
+    }
+
+    const INFO: &[u8] = b"Ode on a Grecian Urn";
+    const PLAINTEXT: &[u8] = b"Beauty is truth, truth beauty";
+
+    // RFC 9180 appendix A.2.1.
+    const BASE: Vector = Vector {
+        ikm_e: "909a9b35d3dc4713a5e72a4da274b55d3d3821a37e5d099e74a647db583a904b",
+        ikm_r: "1ac01f181fdf9f352797655161c58b75c656a6cc2716dcb66372da835542e1df",
+        psk: "",
+        psk_id: "",
+        public_key_e: "1afa08d3dec047a643885163f1180476fa7ddb54c6a8029ea33f95796bf2ac4a",
+        secret_key_e: "f4ec9b33b792c372c1d2c2063507b684ef925b8c75a42dbcbf57d63ccd381600",
+        public_key_r: "4310ee97d88cc1f088a5576c77ab0cf5c3ac797f3d95139c6c84b5429c59662a",
+        secret_key_r: "8057991eef8f1f1af18f4a9491d16a1ce333f695d4db8e38da75975c4478e0fb",
+        shared_secret: "0bbe78490412b4bbea4812666f7916932b828bba79942424abb65244930d69a7",
+        key: "ad2744de8e17f4ebba575b3f5f5a8fa1f69c2a07f6e7500bc60ca6e3e3ec1c91",
+        base_nonce: "5c4d98150661b848853b547f",
+        exporter_secret: "a3b010d4994890e2c6968a36f64470d3c824c8f5029942feb11e7a74b2921922",
+        encryptions: [
+            (0, "5c4d98150661b848853b547f", "1c5250d8034ec2b784ba2cfd69dbdb8af406cfe3ff938e131f0def8c8b60b4db21993c62ce81883d2dd1b51a28"),
+            (1, "5c4d98150661b848853b547e", "6b53c051e4199c518de79594e1c4ab18b96f081549d45ce015be002090bb119e85285337cc95ba5f59992dc98c"),
+            (2, "5c4d98150661b848853b547d", "71146bd6795ccc9c49ce25dda112a48f202ad220559502cef1f34271e0cb4b02b4f10ecac6f48c32f878fae86b"),
+            (4, "5c4d98150661b848853b547b", "63357a2aa291f5a4e5f27db6baa2af8cf77427c7c1a909e0b37214dd47db122bb153495ff0b02e9e54a50dbe16"),
+            (255, "5c4d98150661b848853b5480", "18ab939d63ddec9f6ac2b60d61d36a7375d2070c9b683861110757062c52b8880a5f6b3936da9cd6c23ef2a95c"),
+            (256, "5c4d98150661b848853b557f", "7a4a13e9ef23978e2c520fd4d2e757514ae160cd0cd05e556ef692370ca53076214c0c40d4c728d6ed9e727a5b"),
+        ],
+        exports: [
+            ("", "4bbd6243b8bb54cec311fac9df81841b6fd61f56538a775e7c80a9f40160606e"),
+            ("00", "8c1df14732580e5501b00f82b10a1647b40713191b7c1240ac80e2b68808ba69"),
+            ("54657374436f6e74657874", "5acb09211139c43b3090489a9da433e8a30ee7188ba8b0a9a1ccf0c229283e53"),
+        ],
+    };
+
+    // RFC 9180 appendix A.2.2.
+    const PSK: Vector = Vector {
+        ikm_e: "35706a0b09fb26fb45c39c2f5079c709c7cf98e43afa973f14d88ece7e29c2e3",
+        ikm_r: "26b923eade72941c8a85b09986cdfa3f1296852261adedc52d58d2930269812b",
+        psk: "0247fd33b913760fa1fa51e1892d9f307fbe65eb171e8132c2af18555a738b82",
+        psk_id: "456e6e796e20447572696e206172616e204d6f726961",
+        public_key_e: "2261299c3f40a9afc133b969a97f05e95be2c514e54f3de26cbe5644ac735b04",
+        secret_key_e: "0c35fdf49df7aa01cd330049332c40411ebba36e0c718ebc3edf5845795f6321",
+        public_key_r: "13640af826b722fc04feaa4de2f28fbd5ecc03623b317834e7ff4120dbe73062",
+        secret_key_r: "77d114e0212be51cb1d76fa99dd41cfd4d0166b08caa09074430a6c59ef17879",
+        shared_secret: "4be079c5e77779d0215b3f689595d59e3e9b0455d55662d1f3666ec606e50ea7",
+        key: "600d2fdb0313a7e5c86a9ce9221cd95bed069862421744cfb4ab9d7203a9c019",
+        base_nonce: "112e0465562045b7368653e7",
+        exporter_secret: "73b506dc8b6b4269027f80b0362def5cbb57ee50eed0c2873dac9181f453c5ac",
+        encryptions: [
+            (0, "112e0465562045b7368653e7", "4a177f9c0d6f15cfdf533fb65bf84aecdc6ab16b8b85b4cf65a370e07fc1d78d28fb073214525276f4a89608ff"),
+            (1, "112e0465562045b7368653e6", "5c3cabae2f0b3e124d8d864c116fd8f20f3f56fda988c3573b40b09997fd6c769e77c8eda6cda4f947f5b704a8"),
+            (2, "112e0465562045b7368653e5", "14958900b44bdae9cbe5a528bf933c5c990dbb8e282e6e495adf8205d19da9eb270e3a6f1e0613ab7e757962a4"),
+            (4, "112e0465562045b7368653e3", "c2a7bc09ddb853cf2effb6e8d058e346f7fe0fb3476528c80db6b698415c5f8c50b68a9a355609e96d2117f8d3"),
+            (255, "112e0465562045b736865318", "2414d0788e4bc39a59a26d7bd5d78e111c317d44c37bd5a4c2a1235f2ddc2085c487d406490e75210c958724a7"),
+            (256, "112e0465562045b7368652e7", "c567ae1c3f0f75abe1dd9e4532b422600ed4a6e5b9484dafb1e43ab9f5fd662b28c00e2e81d3cde955dae7e218"),
+        ],
+        exports: [
+            ("", "813c1bfc516c99076ae0f466671f0ba5ff244a41699f7b2417e4c59d46d39f40"),
+            ("00", "2745cf3d5bb65c333658732954ee7af49eb895ce77f8022873a62a13c94cb4e1"),
+            ("54657374436f6e74657874", "ad40e3ae14f21c99bfdebc20ae14ab86f4ca2dc9a4799d200f43a25f99fa78ae"),
+        ],
+    };
+
+    fn array(hex: &str) -> [u8; 32] {
+        hex_to_bytes(hex).as_slice().try_into().unwrap()
+    }
+
+    fn check_vector(mode: u8, vector: &Vector) {
+        let psk = hex_to_bytes(vector.psk);
+        let psk_id = hex_to_bytes(vector.psk_id);
+        let (secret_key_e, public_key_e) = derive_key_pair(&hex_to_bytes(vector.ikm_e)).unwrap();
+        assert_eq!(secret_key_e, array(vector.secret_key_e));
+        assert_eq!(public_key_e, array(vector.public_key_e));
+        let (secret_key_r, public_key_r) = derive_key_pair(&hex_to_bytes(vector.ikm_r)).unwrap();
+        assert_eq!(secret_key_r, array(vector.secret_key_r));
+        assert_eq!(public_key_r, array(vector.public_key_r));
+
+        let (shared_secret, enc) = encap(&public_key_r, &hex_to_bytes(vector.ikm_e)).unwrap();
+        assert_eq!(enc, public_key_e);
+        assert_eq!(shared_secret, array(vector.shared_secret));
+        assert_eq!(decap(&enc, &secret_key_r).unwrap(), shared_secret);
+
+        let (key, base_nonce, exporter_secret) = key_schedule(mode, &shared_secret, INFO, &psk, &psk_id).unwrap();
+        assert_eq!(key, array(vector.key));
+        assert_eq!(base_nonce.to_vec(), hex_to_bytes(vector.base_nonce));
+        assert_eq!(exporter_secret, array(vector.exporter_secret));
+
+        let (enc, mut sender) = setup_sender(mode, &public_key_r, INFO, &psk, &psk_id, &hex_to_bytes(vector.ikm_e)).unwrap();
+        assert_eq!(enc, public_key_e);
+        let mut receiver = setup_receiver(mode, &enc, &secret_key_r, INFO, &psk, &psk_id).unwrap();
+        let mut encryptions = vector.encryptions.iter().peekable();
+        for sequence_number in 0..257 {
+            let aad = format!("Count-{}", sequence_number);
+            let nonce = sender.nonces.nonce_for(sequence_number);
+            let ciphertext = sender.seal(aad.as_bytes(), PLAINTEXT).unwrap();
+            if let Some((_, expected_nonce, expected_ciphertext)) = encryptions.next_if(|encryption| encryption.0 == sequence_number) {
+                assert_eq!(nonce.to_vec(), hex_to_bytes(expected_nonce));
+                assert_eq!(ciphertext, hex_to_bytes(expected_ciphertext));
+            }
+            assert_eq!(receiver.open(aad.as_bytes(), &ciphertext).unwrap(), PLAINTEXT);
+        }
+        assert!(encryptions.next().is_none());
+
+        for (exporter_context, exported) in vector.exports.iter() {
+            assert_eq!(sender.export(&hex_to_bytes(exporter_context), 32).unwrap(), hex_to_bytes(exported));
+            assert_eq!(receiver.export(&hex_to_bytes(exporter_context), 32).unwrap(), hex_to_bytes(exported));
+        }
+    }
+
+    #[test]
+    fn rfc_9180_base_mode() {
+        check_vector(MODE_BASE, &BASE);
+    }
+
+    #[test]
+    fn rfc_9180_psk_mode() {
+        check_vector(MODE_PSK, &PSK);
+    }
+
+    #[test]
+    fn single_shot() {
+        let (secret_key_r, public_key_r) = derive_key_pair(&hex_to_bytes(BASE.ikm_r)).unwrap();
+        let (enc, ciphertext) = seal_base(&public_key_r, INFO, b"Count-0", PLAINTEXT, &hex_to_bytes(BASE.ikm_e)).unwrap();
+        assert_eq!(ciphertext, hex_to_bytes(BASE.encryptions[0].2));
+        assert_eq!(open_base(&enc, &secret_key_r, INFO, b"Count-0", &ciphertext).unwrap(), PLAINTEXT);
+        assert_eq!(open_base(&enc, &secret_key_r, INFO, b"Count-1", &ciphertext), Err(Error::AuthenticationFailed));
+
+        let psk = hex_to_bytes(PSK.psk);
+        let psk_id = hex_to_bytes(PSK.psk_id);
+        let (secret_key_r, public_key_r) = derive_key_pair(&hex_to_bytes(PSK.ikm_r)).unwrap();
+        let (enc, ciphertext) = seal_psk(&public_key_r, INFO, b"Count-0", PLAINTEXT, &psk, &psk_id, &hex_to_bytes(PSK.ikm_e)).unwrap();
+        assert_eq!(ciphertext, hex_to_bytes(PSK.encryptions[0].2));
+        assert_eq!(open_psk(&enc, &secret_key_r, INFO, b"Count-0", &ciphertext, &psk, &psk_id).unwrap(), PLAINTEXT);
+        assert_eq!(open_psk(&enc, &secret_key_r, INFO, b"Count-0", &ciphertext, &psk, b"other id"), Err(Error::AuthenticationFailed));
+    }
+
+    #[test]
+    fn rejects_invalid_psk_inputs() {
+        let (secret_key_r, public_key_r) = derive_key_pair(&hex_to_bytes(PSK.ikm_r)).unwrap();
+        let (_, enc) = derive_key_pair(&hex_to_bytes(PSK.ikm_e)).unwrap();
+        let ikm_e = hex_to_bytes(PSK.ikm_e);
+        let psk = hex_to_bytes(PSK.psk);
+        let psk_id = hex_to_bytes(PSK.psk_id);
+        let cases: [(&[u8], &[u8]); 3] = [(&[], &[]), (&psk, &[]), (&[], &psk_id)];
+        for (psk, psk_id) in cases.iter() {
+            assert!(matches!(setup_psk_sender(&public_key_r, INFO, psk, psk_id, &ikm_e), Err(Error::InvalidPskInputs)));
+            assert!(matches!(setup_psk_receiver(&enc, &secret_key_r, INFO, psk, psk_id), Err(Error::InvalidPskInputs)));
+        }
+        // Base mode takes no PSK at all.
+        assert!(matches!(setup_sender(MODE_BASE, &public_key_r, INFO, &psk, &psk_id, &ikm_e), Err(Error::InvalidPskInputs)));
+        assert!(matches!(setup_receiver(MODE_BASE, &enc, &secret_key_r, INFO, &psk, &[]), Err(Error::InvalidPskInputs)));
+    }
+}
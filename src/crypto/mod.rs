@@ -1,3 +1,18 @@
 pub mod poly1305;
 pub mod chacha20;
-mod utils;
\ No newline at end of file
+pub mod chacha20poly1305;
+pub mod utils;
+pub mod xchacha20poly1305;
+pub mod stream;
+pub mod chacha20poly1305_siv;
+pub mod chacha20poly1305_committing;
+pub mod nonce;
+pub mod chacha20poly1305_legacy;
+pub mod openssh;
+pub mod tls13;
+pub mod wireguard;
+pub mod x25519;
+pub mod ed25519;
+pub mod noise;
+pub mod hpke;
+pub mod rng;
\ No newline at end of file
@@ -0,0 +1,560 @@
+use crate::crypto::chacha20poly1305::{Chacha20Poly1305, Tag};
+use crate::crypto::x25519::X25519;
+use crate::error::{Error, Result};
+use crate::hashes::hkdf;
+use crate::hashes::sha2::SHA256;
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+/// Noise messages, handshake or transport, are at most 65535 bytes.
+pub const MAX_MESSAGE_LENGTH: usize = 65535;
+const HASH_LENGTH: usize = 32;
+const DH_LENGTH: usize = 32;
+
+fn nonce(n: u64) -> [u8; 12] {
+    let mut nonce: [u8; 12] = [0; 12];
+    nonce[4..12].copy_from_slice(&n.to_le_bytes());
+    return nonce;
+}
+
+/// Noise's HKDF: HKDF-SHA256 with the chaining key as salt and empty info,
+/// cut into 32-byte outputs.
+fn noise_hkdf(chaining_key: &[u8; HASH_LENGTH], input_key_material: &[u8], outputs: usize) -> Result<Vec<[u8; HASH_LENGTH]>> {
+    let prk = hkdf::extract::<SHA256>(chaining_key, input_key_material);
+    let okm = hkdf::expand::<SHA256>(&prk, &[], outputs * HASH_LENGTH)?;
+    let mut output_keys: Vec<[u8; HASH_LENGTH]> = Vec::new();
+    for chunk in okm.chunks(HASH_LENGTH) {
+        output_keys.push(chunk.try_into().map_err(|_| Error::InvalidOutputLength)?);
+    }
+    Ok(output_keys)
+}
+
+/// The Noise `CipherState` for the ChaChaPoly cipher: an optional key and a
+/// 64-bit message counter, encoded into the nonce as `0^4 || le64(n)`.
+///
+/// Without a key, encryption and decryption pass data through unchanged, as
+/// the spec requires during the early handshake.
+pub struct CipherState {
+    key: Option<[u8; 32]>,
+    n: u64,
+}
+
+impl CipherState {
+    pub fn initialize_key(&mut self, key: Option<[u8; 32]>) {
+        self.key = key;
+        self.n = 0;
+    }
+
+    pub fn has_key(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub fn set_nonce(&mut self, n: u64) {
+        self.n = n;
+    }
+
+    pub fn encrypt_with_ad(&mut self, ad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let key = match self.key {
+            Some(key) => key,
+            None => return Ok(plaintext.to_vec()),
+        };
+        // 2^64 - 1 is reserved for rekey.
+        if self.n == u64::MAX {
+            return Err(Error::NonceExhausted);
+        }
+        let mut ciphertext = plaintext.to_vec();
+        let tag = Chacha20Poly1305::new(key).encrypt_in_place_detached(&nonce(self.n), ad, &mut ciphertext)?;
+        ciphertext.extend_from_slice(&tag);
+        self.n += 1;
+        Ok(ciphertext)
+    }
+
+    /// Decrypts and verifies; the counter only advances on success.
+    pub fn decrypt_with_ad(&mut self, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let key = match self.key {
+            Some(key) => key,
+            None => return Ok(ciphertext.to_vec()),
+        };
+        if self.n == u64::MAX {
+            return Err(Error::NonceExhausted);
+        }
+        if ciphertext.len() < 16 {
+            return Err(Error::InvalidTagLength);
+        }
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - 16);
+        let tag: Tag = tag.try_into().map_err(|_| Error::InvalidTagLength)?;
+        let mut plaintext = ciphertext.to_vec();
+        Chacha20Poly1305::new(key).decrypt_in_place_detached(&nonce(self.n), ad, &mut plaintext, &tag)?;
+        self.n += 1;
+        Ok(plaintext)
+    }
+
+    /// Replaces the key with the first 32 bytes of encrypting 32 zero bytes
+    /// under nonce 2^64 - 1, without touching the counter.
+    pub fn rekey(&mut self) -> Result<()> {
+        if let Some(key) = self.key {
+            let mut new_key: [u8; 32] = [0; 32];
+            Chacha20Poly1305::new(key).encrypt_in_place_detached(&nonce(u64::MAX), &[], &mut new_key)?;
+            self.key = Some(new_key);
+        }
+        Ok(())
+    }
+
+    pub fn new() -> CipherState {
+        CipherState {
+            key: None,
+            n: 0,
+        }
+    }
+}
+
+/// The Noise `SymmetricState`: the chaining key, the handshake hash and the
+/// `CipherState` keyed from them, using SHA-256.
+pub struct SymmetricState {
+    chaining_key: [u8; HASH_LENGTH],
+    h: [u8; HASH_LENGTH],
+    cipher_state: CipherState,
+}
+
+impl SymmetricState {
+    pub fn mix_key(&mut self, input_key_material: &[u8]) -> Result<()> {
+        let outputs = noise_hkdf(&self.chaining_key, input_key_material, 2)?;
+        self.chaining_key = outputs[0];
+        self.cipher_state.initialize_key(Some(outputs[1]));
+        Ok(())
+    }
+
+    pub fn mix_hash(&mut self, data: &[u8]) {
+        let mut hasher = SHA256::new();
+        hasher.update(&self.h);
+        hasher.update(data);
+        self.h = hasher.digest();
+    }
+
+    pub fn mix_key_and_hash(&mut self, input_key_material: &[u8]) -> Result<()> {
+        let outputs = noise_hkdf(&self.chaining_key, input_key_material, 3)?;
+        self.chaining_key = outputs[0];
+        self.mix_hash(&outputs[1]);
+        self.cipher_state.initialize_key(Some(outputs[2]));
+        Ok(())
+    }
+
+    pub fn handshake_hash(&self) -> [u8; HASH_LENGTH] {
+        self.h
+    }
+
+    pub fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let ciphertext = self.cipher_state.encrypt_with_ad(&self.h, plaintext)?;
+        self.mix_hash(&ciphertext);
+        Ok(ciphertext)
+    }
+
+    pub fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let plaintext = self.cipher_state.decrypt_with_ad(&self.h, ciphertext)?;
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+
+    /// The two transport `CipherState`s: initiator-to-responder first.
+    pub fn split(&self) -> Result<(CipherState, CipherState)> {
+        let outputs = noise_hkdf(&self.chaining_key, &[], 2)?;
+        let mut first = CipherState::new();
+        let mut second = CipherState::new();
+        first.initialize_key(Some(outputs[0]));
+        second.initialize_key(Some(outputs[1]));
+        Ok((first, second))
+    }
+
+    pub fn new(protocol_name: &[u8]) -> SymmetricState {
+        let mut h: [u8; HASH_LENGTH] = [0; HASH_LENGTH];
+        if protocol_name.len() <= HASH_LENGTH {
+            h[..protocol_name.len()].copy_from_slice(protocol_name);
+        } else {
+            let mut hasher = SHA256::new();
+            hasher.update(protocol_name);
+            h = hasher.digest();
+        }
+        SymmetricState {
+            chaining_key: h,
+            h: h,
+            cipher_state: CipherState::new(),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum Token {
+    E,
+    S,
+    EE,
+    ES,
+    SE,
+    SS
+}
+
+/// The handshake patterns supported by [`HandshakeState`].
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum HandshakePattern {
+    /// No static keys on either side.
+    NN,
+    /// Both static keys are transmitted during the handshake.
+    XX,
+    /// The initiator already knows the responder's static key and sends its
+    /// own in the first message.
+    IK
+}
+
+impl HandshakePattern {
+    pub fn name(self) -> &'static str {
+        match self {
+            HandshakePattern::NN => "NN",
+            HandshakePattern::XX => "XX",
+            HandshakePattern::IK => "IK",
+        }
+    }
+
+    // Whether the responder's static key is a pre-message (`<- s`).
+    fn responder_static_premessage(self) -> bool {
+        self == HandshakePattern::IK
+    }
+
+    fn message_patterns(self) -> Vec<Vec<Token>> {
+        match self {
+            HandshakePattern::NN => vec![
+                vec![Token::E],
+                vec![Token::E, Token::EE],
+            ],
+            HandshakePattern::XX => vec![
+                vec![Token::E],
+                vec![Token::E, Token::EE, Token::S, Token::ES],
+                vec![Token::S, Token::SE],
+            ],
+            HandshakePattern::IK => vec![
+                vec![Token::E, Token::ES, Token::S, Token::SS],
+                vec![Token::E, Token::EE, Token::SE],
+            ],
+        }
+    }
+}
+
+/// A Noise handshake for `Noise_<pattern>_25519_ChaChaPoly_SHA256`.
+///
+/// Each side calls `write_message` and `read_message` in turn, starting with
+/// the initiator writing. Once `is_finished` is true, `split` yields the
+/// transport cipher states. The ephemeral secret is supplied by the caller
+/// and must be fresh random bytes for every handshake.
+pub struct HandshakeState {
+    symmetric_state: SymmetricState,
+    initiator: bool,
+    s: Option<[u8; 32]>,
+    e: Option<[u8; 32]>,
+    rs: Option<[u8; 32]>,
+    re: Option<[u8; 32]>,
+    message_patterns: Vec<Vec<Token>>,
+    message_index: usize,
+}
+
+impl HandshakeState {
+    fn public_key(secret: Option<[u8; 32]>) -> Result<[u8; 32]> {
+        match secret {
+            Some(secret) => Ok(X25519::new(secret).public_key()),
+            None => Err(Error::InvalidHandshakeState),
+        }
+    }
+
+    fn dh(secret: Option<[u8; 32]>, public_key: Option<[u8; 32]>) -> Result<[u8; 32]> {
+        match (secret, public_key) {
+            (Some(secret), Some(public_key)) => X25519::new(secret).diffie_hellman(&public_key),
+            _ => Err(Error::InvalidHandshakeState),
+        }
+    }
+
+    // Runs the DH for an `ee`, `es`, `se` or `ss` token from this side.
+    fn mix_dh(&mut self, token: Token) -> Result<()> {
+        let shared_secret = match (token, self.initiator) {
+            (Token::EE, _) => Self::dh(self.e, self.re)?,
+            (Token::ES, true) | (Token::SE, false) => Self::dh(self.e, self.rs)?,
+            (Token::ES, false) | (Token::SE, true) => Self::dh(self.s, self.re)?,
+            (Token::SS, _) => Self::dh(self.s, self.rs)?,
+            _ => return Err(Error::InvalidHandshakeState),
+        };
+        self.symmetric_state.mix_key(&shared_secret)
+    }
+
+    fn next_pattern(&mut self, writing: bool) -> Result<Vec<Token>> {
+        if self.is_finished() || self.message_index.is_multiple_of(2) != (self.initiator == writing) {
+            return Err(Error::InvalidHandshakeState);
+        }
+        let pattern = self.message_patterns[self.message_index].clone();
+        self.message_index += 1;
+        Ok(pattern)
+    }
+
+    pub fn write_message(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        let pattern = self.next_pattern(true)?;
+        let mut message: Vec<u8> = Vec::new();
+        for token in pattern.iter() {
+            match token {
+                Token::E => {
+                    let e_public = Self::public_key(self.e)?;
+                    message.extend_from_slice(&e_public);
+                    self.symmetric_state.mix_hash(&e_public);
+                }
+                Token::S => {
+                    let s_public = Self::public_key(self.s)?;
+                    message.extend_from_slice(&self.symmetric_state.encrypt_and_hash(&s_public)?);
+                }
+                _ => self.mix_dh(*token)?,
+            }
+        }
+        message.extend_from_slice(&self.symmetric_state.encrypt_and_hash(payload)?);
+        if message.len() > MAX_MESSAGE_LENGTH {
+            return Err(Error::MessageTooLong);
+        }
+        Ok(message)
+    }
+
+    /// Processes the peer's next handshake message and returns its payload.
+    /// A failure leaves the handshake unusable; start a new one.
+    pub fn read_message(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+        if message.len() > MAX_MESSAGE_LENGTH {
+            return Err(Error::MessageTooLong);
+        }
+        let pattern = self.next_pattern(false)?;
+        let mut remaining = message;
+        for token in pattern.iter() {
+            match token {
+                Token::E => {
+                    if remaining.len() < DH_LENGTH {
+                        return Err(Error::InvalidPacketLength);
+                    }
+                    let (re, rest) = remaining.split_at(DH_LENGTH);
+                    let re: [u8; 32] = re.try_into().map_err(|_| Error::InvalidPacketLength)?;
+                    self.symmetric_state.mix_hash(&re);
+                    self.re = Some(re);
+                    remaining = rest;
+                }
+                Token::S => {
+                    let length = if self.symmetric_state.cipher_state.has_key() { DH_LENGTH + 16 } else { DH_LENGTH };
+                    if remaining.len() < length {
+                        return Err(Error::InvalidPacketLength);
+                    }
+                    let (encrypted_rs, rest) = remaining.split_at(length);
+                    let rs = self.symmetric_state.decrypt_and_hash(encrypted_rs)?;
+                    self.rs = Some(rs.as_slice().try_into().map_err(|_| Error::InvalidPacketLength)?);
+                    remaining = rest;
+                }
+                _ => self.mix_dh(*token)?,
+            }
+        }
+        self.symmetric_state.decrypt_and_hash(remaining)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.message_index == self.message_patterns.len()
+    }
+
+    /// The handshake hash, usable for channel binding once finished.
+    pub fn handshake_hash(&self) -> [u8; HASH_LENGTH] {
+        self.symmetric_state.handshake_hash()
+    }
+
+    /// The peer's static public key, once known.
+    pub fn remote_static(&self) -> Option<[u8; 32]> {
+        self.rs
+    }
+
+    /// Ends the handshake, returning this side's `(sending, receiving)`
+    /// transport cipher states.
+    pub fn split(self) -> Result<(CipherState, CipherState)> {
+        if !self.is_finished() {
+            return Err(Error::InvalidHandshakeState);
+        }
+        let (initiator_to_responder, responder_to_initiator) = self.symmetric_state.split()?;
+        if self.initiator {
+            Ok((initiator_to_responder, responder_to_initiator))
+        } else {
+            Ok((responder_to_initiator, initiator_to_responder))
+        }
+    }
+
+    /// Starts a handshake. `s` is this side's static secret, `e` its
+    /// ephemeral secret and `rs` the peer's static public key where the
+    /// pattern needs it up front (the initiator in IK).
+    pub fn new(pattern: HandshakePattern, initiator: bool, prologue: &[u8], s: Option<[u8; 32]>, e: [u8; 32], rs: Option<[u8; 32]>) -> Result<HandshakeState> {
+        let protocol_name = format!("Noise_{}_25519_ChaChaPoly_SHA256", pattern.name());
+        let mut symmetric_state = SymmetricState::new(protocol_name.as_bytes());
+        symmetric_state.mix_hash(prologue);
+        if pattern.responder_static_premessage() {
+            let responder_static = if initiator { rs.ok_or(Error::InvalidHandshakeState)? } else { Self::public_key(s)? };
+            symmetric_state.mix_hash(&responder_static);
+        }
+        Ok(HandshakeState {
+            symmetric_state: symmetric_state,
+            initiator: initiator,
+            s: s,
+            e: Some(e),
+            rs: rs,
+            re: None,
+            message_patterns: pattern.message_patterns(),
+            message_index: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INITIATOR_STATIC: [u8; 32] = [0x11; 32];
+    const RESPONDER_STATIC: [u8; 32] = [0x22; 32];
+    const PROLOGUE: &[u8] = b"noise test";
+
+    fn handshake_pair(pattern: HandshakePattern) -> (HandshakeState, HandshakeState) {
+        let responder_public = X25519::new(RESPONDER_STATIC).public_key();
+        let (initiator_static, responder_static, remote_static) = match pattern {
+            HandshakePattern::NN => (None, None, None),
+            HandshakePattern::XX => (Some(INITIATOR_STATIC), Some(RESPONDER_STATIC), None),
+            HandshakePattern::IK => (Some(INITIATOR_STATIC), Some(RESPONDER_STATIC), Some(responder_public)),
+        };
+        let initiator = HandshakeState::new(pattern, true, PROLOGUE, initiator_static, [0x33; 32], remote_static).unwrap();
+        let responder = HandshakeState::new(pattern, false, PROLOGUE, responder_static, [0x44; 32], None).unwrap();
+        (initiator, responder)
+    }
+
+    // Alternates writers until both sides finish, checking every payload
+    // arrives intact.
+    fn run_handshake(initiator: &mut HandshakeState, responder: &mut HandshakeState) {
+        let mut initiator_turn = true;
+        let mut message_number: u8 = 0;
+        while !initiator.is_finished() {
+            let payload = vec![message_number; message_number as usize * 7];
+            let (writer, reader) = if initiator_turn { (&mut *initiator, &mut *responder) } else { (&mut *responder, &mut *initiator) };
+            let message = writer.write_message(&payload).unwrap();
+            assert_eq!(reader.read_message(&message).unwrap(), payload);
+            initiator_turn = !initiator_turn;
+            message_number += 1;
+        }
+        assert!(responder.is_finished());
+        assert_eq!(initiator.handshake_hash(), responder.handshake_hash());
+    }
+
+    fn check_transport(initiator: HandshakeState, responder: HandshakeState) {
+        let (mut initiator_send, mut initiator_receive) = initiator.split().unwrap();
+        let (mut responder_send, mut responder_receive) = responder.split().unwrap();
+        for _ in 0..3 {
+            let ciphertext = initiator_send.encrypt_with_ad(b"ad", b"ping").unwrap();
+            assert_eq!(ciphertext.len(), 4 + 16);
+            assert_eq!(responder_receive.decrypt_with_ad(b"ad", &ciphertext).unwrap(), b"ping");
+            let ciphertext = responder_send.encrypt_with_ad(&[], b"pong").unwrap();
+            assert_eq!(initiator_receive.decrypt_with_ad(&[], &ciphertext).unwrap(), b"pong");
+        }
+        let ciphertext = initiator_send.encrypt_with_ad(&[], b"ping").unwrap();
+        assert_eq!(initiator_receive.decrypt_with_ad(&[], &ciphertext), Err(Error::AuthenticationFailed));
+    }
+
+    #[test]
+    fn nn_handshake() {
+        let (mut initiator, mut responder) = handshake_pair(HandshakePattern::NN);
+        run_handshake(&mut initiator, &mut responder);
+        assert_eq!(initiator.remote_static(), None);
+        assert_eq!(responder.remote_static(), None);
+        check_transport(initiator, responder);
+    }
+
+    #[test]
+    fn xx_handshake() {
+        let (mut initiator, mut responder) = handshake_pair(HandshakePattern::XX);
+        run_handshake(&mut initiator, &mut responder);
+        assert_eq!(initiator.remote_static(), Some(X25519::new(RESPONDER_STATIC).public_key()));
+        assert_eq!(responder.remote_static(), Some(X25519::new(INITIATOR_STATIC).public_key()));
+        check_transport(initiator, responder);
+    }
+
+    #[test]
+    fn ik_handshake() {
+        let (mut initiator, mut responder) = handshake_pair(HandshakePattern::IK);
+        run_handshake(&mut initiator, &mut responder);
+        assert_eq!(responder.remote_static(), Some(X25519::new(INITIATOR_STATIC).public_key()));
+        check_transport(initiator, responder);
+    }
+
+    #[test]
+    fn ik_needs_the_responder_static_key() {
+        let result = HandshakeState::new(HandshakePattern::IK, true, PROLOGUE, Some(INITIATOR_STATIC), [0x33; 32], None);
+        assert!(matches!(result, Err(Error::InvalidHandshakeState)));
+    }
+
+    #[test]
+    fn ik_with_the_wrong_responder_key_fails() {
+        let wrong_key = X25519::new([0x55; 32]).public_key();
+        let mut initiator = HandshakeState::new(HandshakePattern::IK, true, PROLOGUE, Some(INITIATOR_STATIC), [0x33; 32], Some(wrong_key)).unwrap();
+        let (_, mut responder) = handshake_pair(HandshakePattern::IK);
+        let message = initiator.write_message(b"").unwrap();
+        assert_eq!(responder.read_message(&message), Err(Error::AuthenticationFailed));
+    }
+
+    #[test]
+    fn rejects_out_of_turn_messages() {
+        let (mut initiator, mut responder) = handshake_pair(HandshakePattern::XX);
+        assert_eq!(responder.write_message(b""), Err(Error::InvalidHandshakeState));
+        assert_eq!(initiator.read_message(&[0; 32]), Err(Error::InvalidHandshakeState));
+        let message = initiator.write_message(b"").unwrap();
+        assert_eq!(initiator.write_message(b""), Err(Error::InvalidHandshakeState));
+        responder.read_message(&message).unwrap();
+        assert_eq!(responder.read_message(&message), Err(Error::InvalidHandshakeState));
+        assert!(matches!(responder.split(), Err(Error::InvalidHandshakeState)));
+    }
+
+    #[test]
+    fn no_messages_after_the_handshake() {
+        let (mut initiator, mut responder) = handshake_pair(HandshakePattern::NN);
+        run_handshake(&mut initiator, &mut responder);
+        assert_eq!(initiator.write_message(b""), Err(Error::InvalidHandshakeState));
+        assert_eq!(responder.write_message(b""), Err(Error::InvalidHandshakeState));
+        assert_eq!(initiator.read_message(&[]), Err(Error::InvalidHandshakeState));
+    }
+
+    #[test]
+    fn rejects_tampered_handshake_messages() {
+        // Flip a byte of the responder's encrypted static key, and of the
+        // encrypted payload at the end.
+        for index in [40, 95].iter() {
+            let (mut initiator, mut responder) = handshake_pair(HandshakePattern::XX);
+            let message = initiator.write_message(b"").unwrap();
+            responder.read_message(&message).unwrap();
+            let mut message = responder.write_message(b"").unwrap();
+            assert_eq!(message.len(), 32 + 48 + 16);
+            message[*index] ^= 1;
+            assert_eq!(initiator.read_message(&message), Err(Error::AuthenticationFailed));
+        }
+
+        // The first NN message is unauthenticated, but it is hashed into the
+        // handshake, so a changed ephemeral key fails the next message.
+        let (mut initiator, mut responder) = handshake_pair(HandshakePattern::NN);
+        let mut message = initiator.write_message(b"").unwrap();
+        message[0] ^= 1;
+        responder.read_message(&message).unwrap();
+        let message = responder.write_message(b"").unwrap();
+        assert_eq!(initiator.read_message(&message), Err(Error::AuthenticationFailed));
+    }
+
+    #[test]
+    fn rejects_short_and_long_messages() {
+        let (_, mut responder) = handshake_pair(HandshakePattern::XX);
+        assert_eq!(responder.read_message(&[0; 31]), Err(Error::InvalidPacketLength));
+        let (_, mut responder) = handshake_pair(HandshakePattern::XX);
+        assert_eq!(responder.read_message(&vec![0; MAX_MESSAGE_LENGTH + 1]), Err(Error::MessageTooLong));
+    }
+
+    #[test]
+    fn prologues_must_match() {
+        let (mut initiator, _) = handshake_pair(HandshakePattern::NN);
+        let mut responder = HandshakeState::new(HandshakePattern::NN, false, b"other prologue", None, [0x44; 32], None).unwrap();
+        let message = initiator.write_message(b"").unwrap();
+        responder.read_message(&message).unwrap();
+        let message = responder.write_message(b"").unwrap();
+        assert_eq!(initiator.read_message(&message), Err(Error::AuthenticationFailed));
+    }
+}
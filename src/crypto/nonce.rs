@@ -0,0 +1,184 @@
+use crate::error::{Error, Result};
+
+/// A source of 96-bit nonces that never repeats a value. Once a sequence has
+/// handed out its last nonce every further call fails with `NonceExhausted`.
+pub trait NonceSequence {
+    fn next_nonce(&mut self) -> Result<[u8; 12]>;
+}
+
+/// A 96-bit big-endian counter, starting from zero unless told otherwise.
+pub struct CounterNonce {
+    counter: u128,
+}
+
+impl CounterNonce {
+    const LIMIT: u128 = 1 << 96;
+
+    pub fn starting_at(counter: [u8; 12]) -> CounterNonce {
+        let mut counter_bytes: [u8; 16] = [0; 16];
+        counter_bytes[4..16].copy_from_slice(&counter);
+        CounterNonce {
+            counter: u128::from_be_bytes(counter_bytes),
+        }
+    }
+
+    pub fn new() -> CounterNonce {
+        CounterNonce {
+            counter: 0,
+        }
+    }
+}
+
+impl NonceSequence for CounterNonce {
+    fn next_nonce(&mut self) -> Result<[u8; 12]> {
+        if self.counter >= Self::LIMIT {
+            return Err(Error::NonceExhausted);
+        }
+        let mut nonce: [u8; 12] = [0; 12];
+        nonce.copy_from_slice(&self.counter.to_be_bytes()[4..16]);
+        self.counter += 1;
+        Ok(nonce)
+    }
+}
+
+/// The TLS 1.3 (RFC 8446 section 5.3) and QUIC per-record nonce: a static IV
+/// XORed with the 64-bit sequence number, left-padded to 96 bits.
+pub struct XorNonce {
+    iv: [u8; 12],
+    sequence_number: u64,
+    exhausted: bool,
+}
+
+impl XorNonce {
+    /// The sequence number the next nonce will be built from.
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+
+    /// The nonce for an arbitrary sequence number, without advancing.
+    pub fn nonce_for(&self, sequence_number: u64) -> [u8; 12] {
+        let mut nonce = self.iv;
+        let sequence_bytes = sequence_number.to_be_bytes();
+        for i in 0..8 {
+            nonce[4 + i] ^= sequence_bytes[i];
+        }
+        return nonce;
+    }
+
+    pub fn new(iv: [u8; 12]) -> XorNonce {
+        XorNonce {
+            iv: iv,
+            sequence_number: 0,
+            exhausted: false,
+        }
+    }
+}
+
+impl NonceSequence for XorNonce {
+    fn next_nonce(&mut self) -> Result<[u8; 12]> {
+        if self.exhausted {
+            return Err(Error::NonceExhausted);
+        }
+        let nonce = self.nonce_for(self.sequence_number);
+        match self.sequence_number.checked_add(1) {
+            Some(sequence_number) => self.sequence_number = sequence_number,
+            None => self.exhausted = true,
+        }
+        Ok(nonce)
+    }
+}
+
+/// A 32-bit prefix followed by a 64-bit big-endian counter. The prefix should
+/// be chosen at random (or assigned per sender) so independent writers sharing
+/// a key do not overlap.
+pub struct PrefixedCounterNonce {
+    prefix: [u8; 4],
+    counter: u64,
+    exhausted: bool,
+}
+
+impl PrefixedCounterNonce {
+    pub fn new(prefix: [u8; 4]) -> PrefixedCounterNonce {
+        PrefixedCounterNonce {
+            prefix: prefix,
+            counter: 0,
+            exhausted: false,
+        }
+    }
+}
+
+impl NonceSequence for PrefixedCounterNonce {
+    fn next_nonce(&mut self) -> Result<[u8; 12]> {
+        if self.exhausted {
+            return Err(Error::NonceExhausted);
+        }
+        let mut nonce: [u8; 12] = [0; 12];
+        nonce[0..4].copy_from_slice(&self.prefix);
+        nonce[4..12].copy_from_slice(&self.counter.to_be_bytes());
+        match self.counter.checked_add(1) {
+            Some(counter) => self.counter = counter,
+            None => self.exhausted = true,
+        }
+        Ok(nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_counts_up_from_its_start() {
+        let mut nonces = CounterNonce::new();
+        assert_eq!(nonces.next_nonce().unwrap(), [0; 12]);
+        assert_eq!(nonces.next_nonce().unwrap(), [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+        let mut nonces = CounterNonce::starting_at([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff]);
+        assert_eq!(nonces.next_nonce().unwrap(), [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff]);
+        assert_eq!(nonces.next_nonce().unwrap(), [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn counter_exhaustion() {
+        let mut nonces = CounterNonce::starting_at([0xff; 12]);
+        assert_eq!(nonces.next_nonce().unwrap(), [0xff; 12]);
+        assert_eq!(nonces.next_nonce(), Err(Error::NonceExhausted));
+        assert_eq!(nonces.next_nonce(), Err(Error::NonceExhausted));
+    }
+
+    #[test]
+    fn xor_with_the_iv() {
+        let iv = [0x1b, 0x2c, 0x3d, 0x4e, 0x5f, 0x60, 0x71, 0x82, 0x93, 0xa4, 0xb5, 0xc6];
+        let mut nonces = XorNonce::new(iv);
+        assert_eq!(nonces.next_nonce().unwrap(), iv);
+        assert_eq!(nonces.sequence_number(), 1);
+        assert_eq!(nonces.next_nonce().unwrap(), [0x1b, 0x2c, 0x3d, 0x4e, 0x5f, 0x60, 0x71, 0x82, 0x93, 0xa4, 0xb5, 0xc7]);
+        // The IV's first four bytes are never touched.
+        assert_eq!(
+            nonces.nonce_for(0x0102030405060708),
+            [0x1b, 0x2c, 0x3d, 0x4e, 0x5e, 0x62, 0x72, 0x86, 0x96, 0xa2, 0xb2, 0xce]
+        );
+        assert_eq!(nonces.sequence_number(), 2);
+    }
+
+    #[test]
+    fn xor_exhaustion() {
+        let iv = [0x5a; 12];
+        let mut nonces = XorNonce::new(iv);
+        nonces.sequence_number = u64::MAX;
+        assert_eq!(nonces.next_nonce().unwrap(), [0x5a, 0x5a, 0x5a, 0x5a, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5]);
+        assert_eq!(nonces.next_nonce(), Err(Error::NonceExhausted));
+        assert_eq!(nonces.next_nonce(), Err(Error::NonceExhausted));
+    }
+
+    #[test]
+    fn prefixed_counter() {
+        let mut nonces = PrefixedCounterNonce::new([9, 8, 7, 6]);
+        assert_eq!(nonces.next_nonce().unwrap(), [9, 8, 7, 6, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(nonces.next_nonce().unwrap(), [9, 8, 7, 6, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+        nonces.counter = u64::MAX;
+        assert_eq!(nonces.next_nonce().unwrap(), [9, 8, 7, 6, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(nonces.next_nonce(), Err(Error::NonceExhausted));
+    }
+}
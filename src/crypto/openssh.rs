@@ -0,0 +1,160 @@
+use crate::crypto::poly1305::{Poly1305, Tag};
+use crate::crypto::chacha20::{poly1305_key_gen_legacy, ChaCha20};
+use crate::error::{Error, Result};
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+/// The `chacha20-poly1305@openssh.com` SSH transport cipher (OpenSSH
+/// PROTOCOL.chacha20poly1305).
+///
+/// The 64-byte key is split into a main key (first half) and a header key
+/// (second half), each driving the legacy 64-bit nonce ChaCha20 with the
+/// packet sequence number as a big-endian nonce. The header key encrypts only
+/// the 4-byte packet length. The main key's block 0 keys Poly1305 and blocks
+/// from 1 onwards encrypt the packet body. The tag covers the encrypted
+/// length and body and is appended to the packet.
+pub struct OpenSshChacha20Poly1305 {
+    main_key: [u8; 32],
+    header_key: [u8; 32],
+}
+
+impl OpenSshChacha20Poly1305 {
+    pub const LENGTH_FIELD_SIZE: usize = 4;
+
+    /// Seals `body` (everything after the packet length field) and returns
+    /// `encrypted_length || encrypted_body || tag`.
+    pub fn seal(&self, sequence_number: u32, body: &[u8]) -> Result<Vec<u8>> {
+        let length: u32 = body.len().try_into().map_err(|_| Error::MessageTooLong)?;
+        let nonce = Self::nonce(sequence_number);
+        let mut packet: Vec<u8> = Vec::new();
+        packet.extend_from_slice(&length.to_be_bytes());
+        packet.extend_from_slice(body);
+        ChaCha20::new_legacy(self.header_key, nonce).encrypt_in_place(&mut packet[..Self::LENGTH_FIELD_SIZE])?;
+        let mac_key = poly1305_key_gen_legacy(self.main_key, nonce);
+        ChaCha20::new_legacy_with_counter(self.main_key, nonce, 1).encrypt_in_place(&mut packet[Self::LENGTH_FIELD_SIZE..])?;
+        let mut poly1305 = Poly1305::new(mac_key);
+        poly1305.update(&packet);
+        packet.extend_from_slice(&poly1305.finalize());
+        Ok(packet)
+    }
+
+    /// Decrypts the packet length so the caller knows how many more bytes to
+    /// read before calling `open`. The length is not authenticated until
+    /// `open` succeeds.
+    pub fn decrypt_length(&self, sequence_number: u32, encrypted_length: &[u8; 4]) -> Result<u32> {
+        let mut length = *encrypted_length;
+        ChaCha20::new_legacy(self.header_key, Self::nonce(sequence_number)).encrypt_in_place(&mut length)?;
+        Ok(u32::from_be_bytes(length))
+    }
+
+    /// Verifies and decrypts a whole packet as produced by `seal`, returning
+    /// the packet body.
+    pub fn open(&self, sequence_number: u32, packet: &[u8]) -> Result<Vec<u8>> {
+        if packet.len() < Self::LENGTH_FIELD_SIZE + 16 {
+            return Err(Error::InvalidPacketLength);
+        }
+        let (encrypted, tag) = packet.split_at(packet.len() - 16);
+        let tag: Tag = tag.try_into().map_err(|_| Error::InvalidTagLength)?;
+        let encrypted_length: [u8; 4] = encrypted[..Self::LENGTH_FIELD_SIZE].try_into().map_err(|_| Error::InvalidPacketLength)?;
+        let length = self.decrypt_length(sequence_number, &encrypted_length)?;
+        if length as usize != encrypted.len() - Self::LENGTH_FIELD_SIZE {
+            return Err(Error::InvalidPacketLength);
+        }
+        let nonce = Self::nonce(sequence_number);
+        let mac_key = poly1305_key_gen_legacy(self.main_key, nonce);
+        let mut poly1305 = Poly1305::new(mac_key);
+        poly1305.update(encrypted);
+        poly1305.verify(&tag)?;
+        let mut body = encrypted[Self::LENGTH_FIELD_SIZE..].to_vec();
+        ChaCha20::new_legacy_with_counter(self.main_key, nonce, 1).encrypt_in_place(&mut body)?;
+        Ok(body)
+    }
+
+    fn nonce(sequence_number: u32) -> [u8; 8] {
+        (sequence_number as u64).to_be_bytes()
+    }
+
+    pub fn new_from_slice(key: &[u8]) -> Result<OpenSshChacha20Poly1305> {
+        let key: [u8; 64] = key.try_into().map_err(|_| Error::InvalidKeyLength)?;
+        Ok(OpenSshChacha20Poly1305::new(key))
+    }
+
+    pub fn new(key: [u8; 64]) -> OpenSshChacha20Poly1305 {
+        let mut main_key: [u8; 32] = [0; 32];
+        let mut header_key: [u8; 32] = [0; 32];
+        main_key.copy_from_slice(&key[0..32]);
+        header_key.copy_from_slice(&key[32..64]);
+        OpenSshChacha20Poly1305 {
+            main_key: main_key,
+            header_key: header_key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+
+    fn key() -> [u8; 64] {
+        let mut key: [u8; 64] = [0; 64];
+        for i in 0..64 {
+            key[i] = i as u8;
+        }
+        return key;
+    }
+
+    #[test]
+    fn fixed_vector() {
+        let cipher = OpenSshChacha20Poly1305::new([9; 64]);
+        let packet = cipher.seal(3, b"hello ssh packet body").unwrap();
+        assert_eq!(
+            packet,
+            hex_to_bytes("fc4ee7af40978f60490d0db30737854b035401094d5786563f691728a634de1668819c1eee5c543b95")
+        );
+        assert_eq!(cipher.open(3, &packet).unwrap(), b"hello ssh packet body");
+    }
+
+    #[test]
+    fn round_trip() {
+        let cipher = OpenSshChacha20Poly1305::new(key());
+        for length in [0, 1, 59, 60, 61, 200].iter() {
+            let body: Vec<u8> = (0..*length).map(|i| i as u8).collect();
+            let packet = cipher.seal(7, &body).unwrap();
+            assert_eq!(packet.len(), OpenSshChacha20Poly1305::LENGTH_FIELD_SIZE + body.len() + 16);
+            assert_eq!(cipher.open(7, &packet).unwrap(), body);
+            assert!(cipher.open(8, &packet).is_err());
+        }
+    }
+
+    #[test]
+    fn length_decryption() {
+        let cipher = OpenSshChacha20Poly1305::new(key());
+        let packet = cipher.seal(0xfffffffe, &[0x42; 300]).unwrap();
+        let encrypted_length: [u8; 4] = packet[..4].try_into().unwrap();
+        assert_ne!(encrypted_length, 300u32.to_be_bytes());
+        assert_eq!(cipher.decrypt_length(0xfffffffe, &encrypted_length).unwrap(), 300);
+        // The length is encrypted under the header key alone.
+        assert_ne!(OpenSshChacha20Poly1305::new([0; 64]).decrypt_length(0xfffffffe, &encrypted_length).unwrap(), 300);
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let cipher = OpenSshChacha20Poly1305::new(key());
+        let packet = cipher.seal(1, b"some packet body").unwrap();
+
+        let mut tampered_length = packet.clone();
+        tampered_length[3] ^= 1;
+        assert_eq!(cipher.open(1, &tampered_length), Err(Error::InvalidPacketLength));
+
+        for index in [4, packet.len() - 17, packet.len() - 1].iter() {
+            let mut tampered = packet.clone();
+            tampered[*index] ^= 1;
+            assert_eq!(cipher.open(1, &tampered), Err(Error::AuthenticationFailed));
+        }
+
+        assert_eq!(cipher.open(1, &packet[..packet.len() - 1]), Err(Error::InvalidPacketLength));
+        assert_eq!(cipher.open(1, &packet[..19]), Err(Error::InvalidPacketLength));
+    }
+}
@@ -1,8 +1,22 @@
-use crate::crypto::utils::u256::U256;
+use crate::crypto::utils;
+use crate::error::{Error, Result};
+
+use core::convert::TryInto;
+
+// The accumulator and r are held as five 26-bit limbs, so limb products fit
+// in a u64 with room to sum five of them. Blocks leave the accumulator only
+// partially reduced; the full reduction mod 2^130 - 5 happens once, in `tag`.
+const LIMB_MASK: u32 = 0x3FFFFFF;
+
+/// A Poly1305 authenticator, as produced by `finalize`.
+pub type Tag = [u8; 16];
 
 pub struct Poly1305 {
-    prime: U256,
-    accumulator: U256
+    accumulator: [u32; 5],
+    r: [u32; 5],
+    s: u128,
+    buffer: [u8; 16],
+    buffer_length: usize
 }
 
 impl Poly1305 {
@@ -18,8 +32,167 @@ impl Poly1305 {
         return value;
     }
 
-    pub fn mac(&mut self, mut msg: Vec<u8>, key: [u8; 32]) -> [u8; 16] {
-        let mut return_value: [u8; 16] = [0; 16];
+    fn to_limbs(value: u128) -> [u32; 5] {
+        let mut limbs: [u32; 5] = [0; 5];
+        for i in 0..5 {
+            limbs[i] = ((value >> (26 * i)) as u32) & LIMB_MASK;
+        }
+        return limbs;
+    }
+
+    // Adds one 16-byte block, plus 2^128 when `high_bit` is set, and
+    // multiplies by r. Folding uses 2^130 = 5 mod p, so limbs that wrap past
+    // the top are multiplied by 5.
+    fn process_block(&mut self, block: [u8; 16], high_bit: u32) {
+        let block_limbs = Self::to_limbs(u128::from_le_bytes(block));
+        let mut h: [u64; 5] = [0; 5];
+        for i in 0..5 {
+            h[i] = (self.accumulator[i] + block_limbs[i]) as u64;
+        }
+        h[4] += (high_bit as u64) << 24;
+
+        let r: [u64; 5] = [self.r[0] as u64, self.r[1] as u64, self.r[2] as u64, self.r[3] as u64, self.r[4] as u64];
+        let s: [u64; 5] = [0, r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+        let mut d: [u64; 5] = [0; 5];
+        d[0] = h[0] * r[0] + h[1] * s[4] + h[2] * s[3] + h[3] * s[2] + h[4] * s[1];
+        d[1] = h[0] * r[1] + h[1] * r[0] + h[2] * s[4] + h[3] * s[3] + h[4] * s[2];
+        d[2] = h[0] * r[2] + h[1] * r[1] + h[2] * r[0] + h[3] * s[4] + h[4] * s[3];
+        d[3] = h[0] * r[3] + h[1] * r[2] + h[2] * r[1] + h[3] * r[0] + h[4] * s[4];
+        d[4] = h[0] * r[4] + h[1] * r[3] + h[2] * r[2] + h[3] * r[1] + h[4] * r[0];
+
+        let mut carry: u64 = 0;
+        for i in 0..5 {
+            d[i] += carry;
+            carry = d[i] >> 26;
+            self.accumulator[i] = (d[i] as u32) & LIMB_MASK;
+        }
+        let low = self.accumulator[0] as u64 + carry * 5;
+        self.accumulator[0] = (low as u32) & LIMB_MASK;
+        self.accumulator[1] += (low >> 26) as u32;
+    }
+
+    // Fully reduces the accumulator mod 2^130 - 5 in constant time and adds s
+    // mod 2^128.
+    fn tag(&self) -> Tag {
+        let mut h = self.accumulator;
+        let mut carry: u32 = 0;
+        for i in 0..5 {
+            h[i] += carry;
+            carry = h[i] >> 26;
+            h[i] &= LIMB_MASK;
+        }
+        h[0] += carry * 5;
+        carry = h[0] >> 26;
+        h[0] &= LIMB_MASK;
+        h[1] += carry;
+
+        // g = h + 5 - 2^130. If that does not borrow, h >= p and g is the
+        // reduced value.
+        let mut g: [u32; 5] = [0; 5];
+        carry = 5;
+        for i in 0..4 {
+            g[i] = h[i] + carry;
+            carry = g[i] >> 26;
+            g[i] &= LIMB_MASK;
+        }
+        g[4] = (h[4] + carry).wrapping_sub(1 << 26);
+        let select_g = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !select_g) | (g[i] & select_g);
+        }
+
+        let mut value: u128 = 0;
+        for i in 0..5 {
+            value = value.wrapping_add((h[i] as u128) << (26 * i));
+        }
+        value.wrapping_add(self.s).to_le_bytes()
+    }
+
+    /// Adds `data` to the MAC input. Input may be split across calls at any
+    /// point; a partial block is held until more data or `finalize`.
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data.iter() {
+            self.buffer[self.buffer_length] = *byte;
+            self.buffer_length += 1;
+            if self.buffer_length == 16 {
+                self.process_block(self.buffer, 1);
+                self.buffer_length = 0;
+            }
+        }
+    }
+
+    /// `update` followed by zero padding up to the next 16-byte boundary,
+    /// the way the RFC 8439 AEAD construction pads its MAC input.
+    pub fn update_padded(&mut self, data: &[u8]) {
+        self.update(data);
+        if self.buffer_length > 0 {
+            for i in self.buffer_length..16 {
+                self.buffer[i] = 0;
+            }
+            self.process_block(self.buffer, 1);
+            self.buffer_length = 0;
+        }
+    }
+
+    /// The tag for everything passed to `update` since the last `reset`.
+    /// A final partial block is padded with a single 1 bit as RFC 8439
+    /// section 2.5.1 describes.
+    pub fn finalize(mut self) -> Tag {
+        if self.buffer_length > 0 {
+            let mut block: [u8; 16] = [0; 16];
+            block[..self.buffer_length].copy_from_slice(&self.buffer[..self.buffer_length]);
+            block[self.buffer_length] = 1;
+            self.process_block(block, 0);
+        }
+        return self.tag();
+    }
+
+    /// Compares the tag with `expected` in constant time.
+    pub fn verify(self, expected: &Tag) -> Result<()> {
+        if !utils::constant_time_eq(&self.finalize(), expected) {
+            return Err(Error::AuthenticationFailed);
+        }
+        Ok(())
+    }
+
+    /// Discards all input so far, keeping the key. A Poly1305 key is one-time:
+    /// only use this to recompute the tag of the same message, never to MAC a
+    /// different one.
+    pub fn reset(&mut self) {
+        self.accumulator = [0; 5];
+        self.buffer = [0; 16];
+        self.buffer_length = 0;
+    }
+
+    pub fn new_from_slice(key: &[u8]) -> Result<Poly1305> {
+        let key: [u8; 32] = key.try_into().map_err(|_| Error::InvalidKeyLength)?;
+        Ok(Poly1305::new(key))
+    }
+
+    /// `key` is the 32-byte one-time key r || s and must never be used for a
+    /// second message.
+    pub fn new(key: [u8; 32]) -> Poly1305 {
+        let mut r: [u8; 16] = [0; 16];
+        let mut s: [u8; 16] = [0; 16];
+        r.copy_from_slice(&key[0..16]);
+        s.copy_from_slice(&key[16..32]);
+        Poly1305 {
+            accumulator: [0; 5],
+            r: Self::to_limbs(u128::from_le_bytes(Self::clamp(r))),
+            s: u128::from_le_bytes(s),
+            buffer: [0; 16],
+            buffer_length: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+    use crate::crypto::utils::u256::U256;
+
+    fn split_key(key: [u8; 32]) -> (U256, U256) {
         let mut key_upper_bytes: [u8; 16] = [0; 16];
         let mut key_lower_bytes: [u8; 16] = [0; 16];
         for i in 0..32 {
@@ -29,30 +202,168 @@ impl Poly1305 {
                 key_lower_bytes[i % 16] = key[i];
             }
         }
-        let clamped_key = U256::from_16_byte_array(u128::from_le_bytes(Self::clamp(key_upper_bytes)).to_be_bytes());
+        let clamped_key = U256::from_16_byte_array(u128::from_le_bytes(Poly1305::clamp(key_upper_bytes)).to_be_bytes());
         let s = U256::from_16_byte_array(u128::from_le_bytes(key_lower_bytes).to_be_bytes());
-        while msg.len() > 0 {
-            let msg_block_value: U256;
-            if msg.len() >= 16 {
-                msg_block_value = U256::from_message_block(msg.drain(0..16));
-            } else {
-                msg_block_value = U256::from_message_block(msg.drain(0..(msg.len())));
-            }
-            self.accumulator = self.accumulator + msg_block_value;
-            self.accumulator = (clamped_key * self.accumulator) % self.prime;
-        }
-        self.accumulator = self.accumulator + s;
-        let accumulator_bytes = self.accumulator.to_byte_array();
+        (clamped_key, s)
+    }
+
+    fn tag_bytes(value: U256) -> [u8; 16] {
+        let mut return_value: [u8; 16] = [0; 16];
+        let value_bytes = value.to_byte_array();
         for i in 0..16 {
-            return_value[i] = accumulator_bytes[31 - i];
+            return_value[i] = value_bytes[31 - i];
         }
         return return_value;
     }
 
-    pub fn new() -> Poly1305 {
-        Poly1305 {
-            prime: U256::from_hex_string("03fffffffffffffffffffffffffffffffb"),
-            accumulator: U256::zero(),
+    // The original `U256` Poly1305, one full multiply and long division per
+    // block. Far too slow for real use, but simple enough to check the limb
+    // implementation against.
+    fn mac_reference(msg: &[u8], key: [u8; 32]) -> Tag {
+        let prime = (U256::one() << U256::from(130u8)) - U256::from(5u8);
+        let (clamped_key, s) = split_key(key);
+        let mut accumulator = U256::zero();
+        for chunk in msg.chunks(16) {
+            let msg_block_value = U256::from_message_block(chunk.iter().copied());
+            accumulator = accumulator + msg_block_value;
+            accumulator = (clamped_key * accumulator) % prime;
+        }
+        accumulator = accumulator + s;
+        return tag_bytes(accumulator);
+    }
+
+    fn mac(msg: &[u8], key: [u8; 32]) -> Tag {
+        let mut poly1305 = Poly1305::new(key);
+        poly1305.update(msg);
+        poly1305.finalize()
+    }
+
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        return *state;
+    }
+
+    // RFC 8439 section 2.5.2.
+    #[test]
+    fn rfc_8439_test_vector() {
+        let key: [u8; 32] = hex_to_bytes("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b").as_slice().try_into().unwrap();
+        let msg = b"Cryptographic Forum Research Group";
+        let expected = hex_to_bytes("a8061dc1305136c6c22b8baf0c0127a9");
+        assert_eq!(mac(msg, key).to_vec(), expected);
+        assert_eq!(mac_reference(msg, key).to_vec(), expected);
+    }
+
+    #[test]
+    fn limbs_match_reference() {
+        let mut random: u64 = 0x9e3779b97f4a7c15;
+        for length in 0..200 {
+            let mut key: [u8; 32] = [0; 32];
+            for byte in key.iter_mut() {
+                *byte = next_random(&mut random) as u8;
+            }
+            let mut msg: Vec<u8> = vec![0; length];
+            for byte in msg.iter_mut() {
+                *byte = next_random(&mut random) as u8;
+            }
+            assert_eq!(mac(&msg, key), mac_reference(&msg, key), "length {}", length);
         }
     }
-}
\ No newline at end of file
+
+    // All-ones keys and blocks push every limb to its maximum, where missed
+    // carries and the final reduction show up.
+    #[test]
+    fn extreme_values_match_reference() {
+        let keys: [[u8; 32]; 3] = [[0xff; 32], [0; 32], [0x80; 32]];
+        for key in keys.iter() {
+            for length in [0, 1, 15, 16, 17, 32, 64, 199].iter() {
+                let msg = vec![0xff; *length];
+                assert_eq!(mac(&msg, *key), mac_reference(&msg, *key), "length {}", length);
+                let msg = vec![0; *length];
+                assert_eq!(mac(&msg, *key), mac_reference(&msg, *key), "length {}", length);
+            }
+        }
+    }
+
+    #[test]
+    fn update_split_anywhere() {
+        let key: [u8; 32] = [0xa5; 32];
+        let msg: Vec<u8> = (0..100).map(|i| i as u8).collect();
+        let expected = mac(&msg, key);
+        for first in 0..=msg.len() {
+            for second in [first, first + 1, first + 15, first + 16, first + 17].iter() {
+                let second = core::cmp::min(*second, msg.len());
+                let mut poly1305 = Poly1305::new(key);
+                poly1305.update(&msg[..first]);
+                poly1305.update(&msg[first..second]);
+                poly1305.update(&msg[second..]);
+                assert_eq!(poly1305.finalize(), expected, "split at {} and {}", first, second);
+            }
+        }
+
+        let mut poly1305 = Poly1305::new(key);
+        for byte in msg.iter() {
+            poly1305.update(&[*byte]);
+        }
+        assert_eq!(poly1305.finalize(), expected);
+    }
+
+    #[test]
+    fn update_padded_pads_with_zeros() {
+        let key: [u8; 32] = [0x3c; 32];
+        for length in [0, 1, 15, 16, 17, 33].iter() {
+            let msg = vec![0x77; *length];
+            let mut padded = msg.clone();
+            padded.resize(length.div_ceil(16) * 16, 0);
+            let mut poly1305 = Poly1305::new(key);
+            poly1305.update(&msg[..length / 2]);
+            poly1305.update_padded(&msg[length / 2..]);
+            poly1305.update(b"tail");
+            padded.extend_from_slice(b"tail");
+            assert_eq!(poly1305.finalize(), mac(&padded, key), "length {}", length);
+        }
+    }
+
+    #[test]
+    fn reset_discards_input() {
+        let key: [u8; 32] = [0x5a; 32];
+        let mut poly1305 = Poly1305::new(key);
+        poly1305.update(b"some data that is not part of the message");
+        poly1305.reset();
+        poly1305.update(b"message");
+        assert_eq!(poly1305.finalize(), mac(b"message", key));
+
+        // A reset between full blocks, with nothing buffered, still clears.
+        let mut poly1305 = Poly1305::new(key);
+        poly1305.update(&[1; 32]);
+        poly1305.reset();
+        assert_eq!(poly1305.finalize(), mac(&[], key));
+    }
+
+    #[test]
+    fn verify() {
+        let key: [u8; 32] = [0x11; 32];
+        let tag = mac(b"message", key);
+        let mut poly1305 = Poly1305::new(key);
+        poly1305.update(b"message");
+        assert_eq!(poly1305.verify(&tag), Ok(()));
+        for i in 0..16 {
+            let mut bad_tag = tag;
+            bad_tag[i] ^= 0x80;
+            let mut poly1305 = Poly1305::new(key);
+            poly1305.update(b"message");
+            assert_eq!(poly1305.verify(&bad_tag), Err(Error::AuthenticationFailed));
+        }
+        let mut poly1305 = Poly1305::new(key);
+        poly1305.update(b"messagf");
+        assert_eq!(poly1305.verify(&tag), Err(Error::AuthenticationFailed));
+    }
+
+    #[test]
+    fn new_from_slice_checks_the_length() {
+        assert!(Poly1305::new_from_slice(&[0; 32]).is_ok());
+        assert!(matches!(Poly1305::new_from_slice(&[0; 31]), Err(Error::InvalidKeyLength)));
+        assert!(matches!(Poly1305::new_from_slice(&[0; 33]), Err(Error::InvalidKeyLength)));
+    }
+}
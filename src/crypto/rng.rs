@@ -0,0 +1,280 @@
+use crate::crypto::chacha20::ChaCha;
+use crate::error::{Error, Result};
+
+/// A deterministic ChaCha random number generator with `ROUNDS` rounds.
+///
+/// The 32-byte seed is used as the key of the original 64-bit nonce /
+/// 64-bit counter ChaCha layout. The nonce selects one of 2^64 independent
+/// streams and the counter gives each stream 2^68 32-bit words before it
+/// wraps. Output is the keystream in order, read as little-endian words. The
+/// same seed, stream and word position always give the same output.
+///
+/// With fast key erasure, the first 32 bytes of every block replace the key
+/// and only the second half is output. Earlier output then cannot be
+/// recomputed from the generator's current state. Word positions still
+/// count blocks, including the erased halves, but the position and stream
+/// cannot be changed: every block is under a key the next block erases.
+pub struct ChaChaRng<const ROUNDS: usize> {
+    key: [u8; 32],
+    stream: u64,
+    block_count: u64,
+    buffer: [u8; 64],
+    index: usize,
+    fast_key_erasure: bool,
+}
+
+pub type ChaCha20Rng = ChaChaRng<20>;
+pub type ChaCha12Rng = ChaChaRng<12>;
+pub type ChaCha8Rng = ChaChaRng<8>;
+
+impl<const ROUNDS: usize> ChaChaRng<ROUNDS> {
+    fn refill(&mut self) {
+        let mut cipher = ChaCha::<ROUNDS>::new_legacy_with_counter(self.key, self.stream.to_le_bytes(), self.block_count);
+        // A freshly created legacy cipher can always produce its first block.
+        self.buffer = match cipher.chacha_block() {
+            Ok(block) => block,
+            Err(_) => unreachable!(),
+        };
+        self.block_count = self.block_count.wrapping_add(1);
+        self.index = 0;
+        if self.fast_key_erasure {
+            self.key.copy_from_slice(&self.buffer[0..32]);
+            for i in 0..32 {
+                self.buffer[i] = 0;
+            }
+            self.index = 32;
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        if self.index == 64 {
+            self.refill();
+        }
+        let word = u32::from_le_bytes([self.buffer[self.index], self.buffer[self.index + 1], self.buffer[self.index + 2], self.buffer[self.index + 3]]);
+        self.index += 4;
+        return word;
+    }
+
+    /// Two consecutive words, low word first.
+    pub fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        let high = self.next_u32() as u64;
+        (high << 32) | low
+    }
+
+    /// Fills `buffer` from the keystream. Output is consumed in whole words,
+    /// so the unused bytes of a final partial word are discarded and the
+    /// position stays word aligned.
+    pub fn fill_bytes(&mut self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(4) {
+            let word = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+    }
+
+    /// The number of 32-bit words produced so far in the current stream.
+    pub fn word_pos(&self) -> u128 {
+        let mut block_index = self.block_count as u128;
+        if self.index != 64 {
+            // The buffered block, one behind the counter mod 2^64.
+            block_index = (block_index + (1 << 64) - 1) % (1 << 64);
+        }
+        return block_index * 16 + (self.index % 64 / 4) as u128;
+    }
+
+    /// Moves to word `word_pos` (taken mod 2^68) of the current stream.
+    /// Fails with `KeyErased` under fast key erasure.
+    pub fn set_word_pos(&mut self, word_pos: u128) -> Result<()> {
+        if self.fast_key_erasure {
+            return Err(Error::KeyErased);
+        }
+        self.block_count = (word_pos / 16) as u64;
+        let word_index = (word_pos % 16) as usize;
+        self.index = 64;
+        if word_index > 0 {
+            self.refill();
+            self.index = word_index * 4;
+        }
+        Ok(())
+    }
+
+    pub fn stream(&self) -> u64 {
+        self.stream
+    }
+
+    /// Switches to another stream, keeping the word position. Fails with
+    /// `KeyErased` under fast key erasure.
+    pub fn set_stream(&mut self, stream: u64) -> Result<()> {
+        if self.fast_key_erasure {
+            return Err(Error::KeyErased);
+        }
+        let word_pos = self.word_pos();
+        self.stream = stream;
+        self.set_word_pos(word_pos)
+    }
+
+    pub fn from_seed(seed: [u8; 32]) -> ChaChaRng<ROUNDS> {
+        ChaChaRng {
+            key: seed,
+            stream: 0,
+            block_count: 0,
+            buffer: [0; 64],
+            index: 64,
+            fast_key_erasure: false,
+        }
+    }
+
+    pub fn from_seed_with_key_erasure(seed: [u8; 32]) -> ChaChaRng<ROUNDS> {
+        let mut rng = Self::from_seed(seed);
+        rng.fast_key_erasure = true;
+        return rng;
+    }
+}
+
+/// The keystream of a `ChaCha` cipher as an iterator of bytes, starting from the
+/// cipher's current position. Ends when the block counter runs out.
+pub struct Keystream<const ROUNDS: usize> {
+    cipher: ChaCha<ROUNDS>,
+}
+
+impl<const ROUNDS: usize> Iterator for Keystream<ROUNDS> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let mut byte: [u8; 1] = [0];
+        match self.cipher.apply_keystream(&mut byte) {
+            Ok(()) => Some(byte[0]),
+            Err(_) => None,
+        }
+    }
+}
+
+impl<const ROUNDS: usize> Keystream<ROUNDS> {
+    pub fn new(cipher: ChaCha<ROUNDS>) -> Keystream<ROUNDS> {
+        Keystream {
+            cipher: cipher,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(rng: &mut ChaCha20Rng, count: usize) -> Vec<u32> {
+        let mut output: Vec<u32> = Vec::new();
+        for _ in 0..count {
+            output.push(rng.next_u32());
+        }
+        return output;
+    }
+
+    #[test]
+    fn output_is_the_legacy_keystream() {
+        let seed: [u8; 32] = [5; 32];
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        let mut expected: [u8; 128] = [0; 128];
+        ChaCha::<20>::new_legacy(seed, [0; 8]).apply_keystream(&mut expected).unwrap();
+        let mut output: [u8; 128] = [0; 128];
+        rng.fill_bytes(&mut output);
+        assert_eq!(output.to_vec(), expected.to_vec());
+        assert_eq!(rng.word_pos(), 32);
+    }
+
+    #[test]
+    fn seeking_repeats_output() {
+        let mut rng = ChaCha20Rng::from_seed([5; 32]);
+        let expected = words(&mut rng, 40);
+        for word_pos in [1, 0, 17, 16, 39, 5].iter() {
+            rng.set_word_pos(*word_pos as u128).unwrap();
+            assert_eq!(rng.word_pos(), *word_pos as u128);
+            assert_eq!(words(&mut rng, 40 - word_pos), expected[*word_pos..].to_vec());
+        }
+
+        // Seeking to the current position changes nothing.
+        rng.set_word_pos(3).unwrap();
+        let position = rng.word_pos();
+        rng.set_word_pos(position).unwrap();
+        assert_eq!(rng.next_u32(), expected[3]);
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        let mut rng = ChaCha20Rng::from_seed([5; 32]);
+        let stream_0 = words(&mut rng, 20);
+        rng.set_stream(1).unwrap();
+        assert_eq!(rng.stream(), 1);
+        assert_eq!(rng.word_pos(), 20);
+        let stream_1 = words(&mut rng, 4);
+        assert_ne!(stream_1, stream_0[..4].to_vec());
+        rng.set_stream(0).unwrap();
+        rng.set_word_pos(20).unwrap();
+        let mut expected = ChaCha20Rng::from_seed([5; 32]);
+        expected.set_word_pos(20).unwrap();
+        assert_eq!(words(&mut rng, 4), words(&mut expected, 4));
+    }
+
+    #[test]
+    fn key_erasure_outputs_second_halves() {
+        let seed: [u8; 32] = [5; 32];
+        let mut plain = ChaCha20Rng::from_seed(seed);
+        let first_block = words(&mut plain, 16);
+        let mut rng = ChaCha20Rng::from_seed_with_key_erasure(seed);
+        assert_eq!(words(&mut rng, 8), first_block[8..].to_vec());
+        assert_eq!(rng.word_pos(), 16);
+
+        // The next block is under the erased half of the first as key.
+        let mut next_key: [u8; 32] = [0; 32];
+        for i in 0..8 {
+            next_key[i * 4..i * 4 + 4].copy_from_slice(&first_block[i].to_le_bytes());
+        }
+        let mut expected = ChaCha20Rng::from_seed(next_key);
+        expected.set_word_pos(24).unwrap();
+        assert_eq!(words(&mut rng, 8), words(&mut expected, 8));
+    }
+
+    #[test]
+    fn word_pos_wraps_with_the_counter() {
+        let seed: [u8; 32] = [5; 32];
+        let mut last_block: [u8; 64] = [0; 64];
+        ChaCha::<20>::new_legacy_with_counter(seed, [0; 8], u64::MAX).apply_keystream(&mut last_block).unwrap();
+
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        rng.set_word_pos((1 << 68) - 16).unwrap();
+        assert_eq!(rng.word_pos(), (1 << 68) - 16);
+        assert_eq!(rng.next_u32(), u32::from_le_bytes([last_block[0], last_block[1], last_block[2], last_block[3]]));
+        assert_eq!(rng.word_pos(), (1 << 68) - 15);
+
+        rng.set_word_pos((1 << 68) - 1).unwrap();
+        assert_eq!(rng.word_pos(), (1 << 68) - 1);
+        assert_eq!(rng.next_u32(), u32::from_le_bytes([last_block[60], last_block[61], last_block[62], last_block[63]]));
+        assert_eq!(rng.word_pos(), 0);
+        let mut start = ChaCha20Rng::from_seed(seed);
+        assert_eq!(words(&mut rng, 20), words(&mut start, 20));
+        assert_eq!(rng.word_pos(), 20);
+
+        rng.set_word_pos((1 << 68) + 3).unwrap();
+        assert_eq!(rng.word_pos(), 3);
+    }
+
+    #[test]
+    fn key_erasure_rejects_seeking() {
+        let mut rng = ChaCha20Rng::from_seed_with_key_erasure([5; 32]);
+        let mut copy = ChaCha20Rng::from_seed_with_key_erasure([5; 32]);
+        rng.next_u32();
+        copy.next_u32();
+        let position = rng.word_pos();
+        assert_eq!(rng.set_word_pos(1), Err(Error::KeyErased));
+        assert_eq!(rng.set_word_pos(position), Err(Error::KeyErased));
+        assert_eq!(rng.set_stream(1), Err(Error::KeyErased));
+        assert_eq!(rng.stream(), 0);
+        assert_eq!(rng.word_pos(), position);
+        assert_eq!(words(&mut rng, 30), words(&mut copy, 30));
+    }
+
+    #[test]
+    fn keystream_iterator_stops_at_the_end() {
+        let cipher = ChaCha::<20>::new_with_counter([1; 32], [2; 12], u32::MAX);
+        assert_eq!(Keystream::new(cipher).count(), 64);
+    }
+}
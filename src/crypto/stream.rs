@@ -0,0 +1,264 @@
+use crate::crypto::chacha20poly1305::{Chacha20Poly1305, Tag};
+use crate::error::{Error, Result};
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+/// Length of the per-stream nonce prefix; it must never repeat under one key.
+pub const NONCE_PREFIX_LENGTH: usize = 7;
+
+fn segment_nonce(prefix: &[u8; NONCE_PREFIX_LENGTH], counter: u32, last_segment: bool) -> [u8; 12] {
+    let mut nonce: [u8; 12] = [0; 12];
+    nonce[..NONCE_PREFIX_LENGTH].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LENGTH..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last_segment as u8;
+    return nonce;
+}
+
+/// The STREAM online AEAD construction (Hoang, Reyhanitabar, Rogaway and
+/// Vizár) over ChaCha20-Poly1305.
+///
+/// A message is split into segments, each sealed under the nonce
+/// `prefix || counter || last_segment_flag`: a 7-byte per-stream prefix, a
+/// 32-bit big-endian segment counter and a flag byte set to 1 only on the final
+/// segment. Reordering, dropping or splicing segments changes the nonce they
+/// are opened under, and truncation is caught because the final flag is never
+/// seen. Each sealed segment is the ciphertext followed by its 16-byte tag.
+///
+/// Non-final segments use counters 0 to 2^32 - 2, so the final segment can
+/// always be sealed, at counter 2^32 - 1 at the latest.
+pub struct StreamEncryptor {
+    aead: Chacha20Poly1305,
+    prefix: [u8; NONCE_PREFIX_LENGTH],
+    counter: u32,
+}
+
+impl StreamEncryptor {
+    pub fn encrypt_next(&mut self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.next_nonce(false)?;
+        Self::seal(&self.aead, &nonce, aad, plaintext)
+    }
+
+    /// Seals the final segment, which may be empty, and ends the stream.
+    pub fn encrypt_last(mut self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.next_nonce(true)?;
+        Self::seal(&self.aead, &nonce, aad, plaintext)
+    }
+
+    fn next_nonce(&mut self, last_segment: bool) -> Result<[u8; 12]> {
+        let nonce = segment_nonce(&self.prefix, self.counter, last_segment);
+        if !last_segment {
+            self.counter = self.counter.checked_add(1).ok_or(Error::CounterExhausted)?;
+        }
+        Ok(nonce)
+    }
+
+    fn seal(aead: &Chacha20Poly1305, nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut segment = plaintext.to_vec();
+        aead.encrypt_in_place(nonce, aad, &mut segment)?;
+        Ok(segment)
+    }
+
+    pub fn new(key: [u8; 32], prefix: [u8; NONCE_PREFIX_LENGTH]) -> StreamEncryptor {
+        StreamEncryptor {
+            aead: Chacha20Poly1305::new(key),
+            prefix: prefix,
+            counter: 0,
+        }
+    }
+}
+
+/// Opens the segments produced by [`StreamEncryptor`] in order.
+///
+/// Plaintext returned by `decrypt_next` is authentic, but the stream is only
+/// complete once `decrypt_last` has succeeded; `finish` reports a stream that
+/// ended early.
+pub struct StreamDecryptor {
+    aead: Chacha20Poly1305,
+    prefix: [u8; NONCE_PREFIX_LENGTH],
+    counter: u32,
+    finished: bool,
+}
+
+impl StreamDecryptor {
+    pub fn decrypt_next(&mut self, aad: &[u8], segment: &[u8]) -> Result<Vec<u8>> {
+        if self.finished {
+            return Err(Error::StreamFinished);
+        }
+        // The last counter is reserved for the final segment.
+        let next_counter = self.counter.checked_add(1).ok_or(Error::CounterExhausted)?;
+        let nonce = segment_nonce(&self.prefix, self.counter, false);
+        let plaintext = Self::open(&self.aead, &nonce, aad, segment)?;
+        self.counter = next_counter;
+        Ok(plaintext)
+    }
+
+    pub fn decrypt_last(&mut self, aad: &[u8], segment: &[u8]) -> Result<Vec<u8>> {
+        if self.finished {
+            return Err(Error::StreamFinished);
+        }
+        let nonce = segment_nonce(&self.prefix, self.counter, true);
+        let plaintext = Self::open(&self.aead, &nonce, aad, segment)?;
+        self.finished = true;
+        Ok(plaintext)
+    }
+
+    /// Ends decryption, failing with `TruncatedStream` if the final segment
+    /// was never opened.
+    pub fn finish(self) -> Result<()> {
+        if !self.finished {
+            return Err(Error::TruncatedStream);
+        }
+        Ok(())
+    }
+
+    fn open(aead: &Chacha20Poly1305, nonce: &[u8; 12], aad: &[u8], segment: &[u8]) -> Result<Vec<u8>> {
+        if segment.len() < 16 {
+            return Err(Error::InvalidTagLength);
+        }
+        let (ciphertext, tag) = segment.split_at(segment.len() - 16);
+        let tag: Tag = tag.try_into().map_err(|_| Error::InvalidTagLength)?;
+        let mut plaintext = ciphertext.to_vec();
+        aead.decrypt_in_place_detached(nonce, aad, &mut plaintext, &tag)?;
+        Ok(plaintext)
+    }
+
+    pub fn new(key: [u8; 32], prefix: [u8; NONCE_PREFIX_LENGTH]) -> StreamDecryptor {
+        StreamDecryptor {
+            aead: Chacha20Poly1305::new(key),
+            prefix: prefix,
+            counter: 0,
+            finished: false,
+        }
+    }
+}
+
+/// Splits `plaintext` into `segment_size` chunks and seals them as one stream.
+/// An empty plaintext produces a single empty final segment.
+pub fn encrypt_segments(key: [u8; 32], prefix: [u8; NONCE_PREFIX_LENGTH], segment_size: usize, plaintext: &[u8]) -> Result<Vec<Vec<u8>>> {
+    if segment_size == 0 {
+        return Err(Error::InvalidSegmentSize);
+    }
+    let mut encryptor = StreamEncryptor::new(key, prefix);
+    let mut segments: Vec<Vec<u8>> = Vec::new();
+    let mut chunks = plaintext.chunks(segment_size).peekable();
+    while let Some(chunk) = chunks.next() {
+        if chunks.peek().is_none() {
+            segments.push(encryptor.encrypt_last(&[], chunk)?);
+            return Ok(segments);
+        }
+        segments.push(encryptor.encrypt_next(&[], chunk)?);
+    }
+    segments.push(encryptor.encrypt_last(&[], &[])?);
+    Ok(segments)
+}
+
+/// Opens every segment of a stream sealed by [`encrypt_segments`], treating
+/// the last one as final, and returns the concatenated plaintext.
+pub fn decrypt_segments(key: [u8; 32], prefix: [u8; NONCE_PREFIX_LENGTH], segments: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let mut decryptor = StreamDecryptor::new(key, prefix);
+    let mut plaintext: Vec<u8> = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        if i + 1 == segments.len() {
+            plaintext.extend_from_slice(&decryptor.decrypt_last(&[], segment)?);
+        } else {
+            plaintext.extend_from_slice(&decryptor.decrypt_next(&[], segment)?);
+        }
+    }
+    decryptor.finish()?;
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [0x42; 32];
+    const PREFIX: [u8; NONCE_PREFIX_LENGTH] = [1, 2, 3, 4, 5, 6, 7];
+
+    #[test]
+    fn round_trip() {
+        let plaintext: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        for length in [0, 1, 63, 64, 65, 1000].iter() {
+            for segment_size in [1, 64, 100, 2000].iter() {
+                let segments = encrypt_segments(KEY, PREFIX, *segment_size, &plaintext[..*length]).unwrap();
+                let expected_segments = if *length == 0 { 1 } else { length.div_ceil(*segment_size) };
+                assert_eq!(segments.len(), expected_segments);
+                assert_eq!(decrypt_segments(KEY, PREFIX, &segments).unwrap(), plaintext[..*length].to_vec());
+            }
+        }
+        assert_eq!(encrypt_segments(KEY, PREFIX, 0, b"data"), Err(Error::InvalidSegmentSize));
+    }
+
+    #[test]
+    fn segments_carry_their_aad() {
+        let mut encryptor = StreamEncryptor::new(KEY, PREFIX);
+        let first = encryptor.encrypt_next(b"header 0", b"first").unwrap();
+        let last = encryptor.encrypt_last(b"header 1", b"last").unwrap();
+        let mut decryptor = StreamDecryptor::new(KEY, PREFIX);
+        assert_eq!(decryptor.decrypt_next(b"header 1", &first), Err(Error::AuthenticationFailed));
+        assert_eq!(decryptor.decrypt_next(b"header 0", &first).unwrap(), b"first");
+        assert_eq!(decryptor.decrypt_last(b"header 1", &last).unwrap(), b"last");
+        assert_eq!(decryptor.finish(), Ok(()));
+    }
+
+    #[test]
+    fn detects_truncation() {
+        let segments = encrypt_segments(KEY, PREFIX, 10, &[7; 35]).unwrap();
+        assert_eq!(segments.len(), 4);
+        // The last remaining segment was not sealed as final.
+        assert_eq!(decrypt_segments(KEY, PREFIX, &segments[..3]), Err(Error::AuthenticationFailed));
+
+        let mut decryptor = StreamDecryptor::new(KEY, PREFIX);
+        for segment in segments[..3].iter() {
+            decryptor.decrypt_next(&[], segment).unwrap();
+        }
+        assert_eq!(decryptor.finish(), Err(Error::TruncatedStream));
+    }
+
+    #[test]
+    fn detects_reordering() {
+        let mut segments = encrypt_segments(KEY, PREFIX, 10, &[7; 35]).unwrap();
+        segments.swap(0, 1);
+        assert_eq!(decrypt_segments(KEY, PREFIX, &segments), Err(Error::AuthenticationFailed));
+        segments.swap(0, 1);
+        segments.swap(2, 3);
+        assert_eq!(decrypt_segments(KEY, PREFIX, &segments), Err(Error::AuthenticationFailed));
+        // Another prefix is another stream.
+        segments.swap(2, 3);
+        assert_eq!(decrypt_segments(KEY, [0; NONCE_PREFIX_LENGTH], &segments), Err(Error::AuthenticationFailed));
+    }
+
+    #[test]
+    fn no_segments_after_the_final_one() {
+        let segments = encrypt_segments(KEY, PREFIX, 10, &[7; 15]).unwrap();
+        let mut decryptor = StreamDecryptor::new(KEY, PREFIX);
+        decryptor.decrypt_next(&[], &segments[0]).unwrap();
+        decryptor.decrypt_last(&[], &segments[1]).unwrap();
+        assert_eq!(decryptor.decrypt_next(&[], &segments[1]), Err(Error::StreamFinished));
+        assert_eq!(decryptor.decrypt_last(&[], &segments[1]), Err(Error::StreamFinished));
+        assert_eq!(decryptor.finish(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_short_segments() {
+        let mut decryptor = StreamDecryptor::new(KEY, PREFIX);
+        assert_eq!(decryptor.decrypt_next(&[], &[0; 15]), Err(Error::InvalidTagLength));
+    }
+
+    #[test]
+    fn last_counter_is_kept_for_the_final_segment() {
+        let mut encryptor = StreamEncryptor::new(KEY, PREFIX);
+        encryptor.counter = u32::MAX - 1;
+        let next = encryptor.encrypt_next(&[], b"next").unwrap();
+        assert_eq!(encryptor.encrypt_next(&[], b"too many"), Err(Error::CounterExhausted));
+        let last = encryptor.encrypt_last(&[], b"last").unwrap();
+
+        let mut decryptor = StreamDecryptor::new(KEY, PREFIX);
+        decryptor.counter = u32::MAX - 1;
+        assert_eq!(decryptor.decrypt_next(&[], &next).unwrap(), b"next");
+        assert_eq!(decryptor.decrypt_next(&[], &last), Err(Error::CounterExhausted));
+        assert_eq!(decryptor.decrypt_last(&[], &last).unwrap(), b"last");
+        assert_eq!(decryptor.finish(), Ok(()));
+    }
+}
@@ -0,0 +1,226 @@
+use crate::crypto::chacha20poly1305::Chacha20Poly1305;
+use crate::crypto::nonce::{NonceSequence, XorNonce};
+use crate::error::{Error, Result};
+use crate::hashes::hkdf;
+use crate::hashes::sha2::SHA256;
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum ContentType {
+    ChangeCipherSpec,
+    Alert,
+    Handshake,
+    ApplicationData
+}
+
+impl ContentType {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            ContentType::ChangeCipherSpec => 20,
+            ContentType::Alert => 21,
+            ContentType::Handshake => 22,
+            ContentType::ApplicationData => 23,
+        }
+    }
+
+    pub fn from_byte(value: u8) -> Result<ContentType> {
+        match value {
+            20 => Ok(ContentType::ChangeCipherSpec),
+            21 => Ok(ContentType::Alert),
+            22 => Ok(ContentType::Handshake),
+            23 => Ok(ContentType::ApplicationData),
+            _ => Err(Error::UnexpectedMessage),
+        }
+    }
+}
+
+/// HKDF-Expand-Label (RFC 8446 section 7.1) with SHA-256.
+pub fn hkdf_expand_label(secret: &[u8], label: &str, context: &[u8], length: usize) -> Result<Vec<u8>> {
+    let full_label = format!("tls13 {}", label);
+    if length > u16::MAX as usize || full_label.len() > 255 || context.len() > 255 {
+        return Err(Error::InvalidOutputLength);
+    }
+    let mut hkdf_label: Vec<u8> = Vec::new();
+    hkdf_label.extend_from_slice(&(length as u16).to_be_bytes());
+    hkdf_label.push(full_label.len() as u8);
+    hkdf_label.extend_from_slice(full_label.as_bytes());
+    hkdf_label.push(context.len() as u8);
+    hkdf_label.extend_from_slice(context);
+    hkdf::expand::<SHA256>(secret, &hkdf_label, length)
+}
+
+/// One direction of TLS 1.3 record protection for
+/// TLS_CHACHA20_POLY1305_SHA256 (RFC 8446 section 5.2).
+///
+/// `protect` turns a plaintext fragment into a complete TLSCiphertext record
+/// (header, encrypted TLSInnerPlaintext and tag) and `unprotect` reverses it.
+/// Each record uses the write IV XORed with the record sequence number as its
+/// nonce, and the 5-byte record header as AAD. A failed `unprotect` still
+/// consumes a sequence number; TLS treats it as fatal to the connection.
+pub struct RecordProtection {
+    aead: Chacha20Poly1305,
+    nonces: XorNonce,
+}
+
+impl RecordProtection {
+    pub const MAX_FRAGMENT_LENGTH: usize = 1 << 14;
+    pub const MAX_CIPHERTEXT_LENGTH: usize = (1 << 14) + 256;
+    const HEADER_LENGTH: usize = 5;
+    const OPAQUE_TYPE: u8 = 23;
+    const LEGACY_RECORD_VERSION: [u8; 2] = [0x03, 0x03];
+
+    /// Seals `fragment` as `content_type` with `padding_length` zero bytes of
+    /// padding after the inner content type. Fragment, content type and
+    /// padding together may not exceed 2^14 + 1 bytes (RFC 8446 section 5.4),
+    /// so a full-size fragment cannot be padded at all.
+    pub fn protect(&mut self, content_type: ContentType, fragment: &[u8], padding_length: usize) -> Result<Vec<u8>> {
+        let inner_plaintext_length = match padding_length.checked_add(fragment.len() + 1) {
+            Some(length) if length <= Self::MAX_FRAGMENT_LENGTH + 1 => length,
+            _ => return Err(Error::RecordOverflow),
+        };
+        let ciphertext_length = inner_plaintext_length + 16;
+        let mut record: Vec<u8> = Vec::new();
+        record.push(Self::OPAQUE_TYPE);
+        record.extend_from_slice(&Self::LEGACY_RECORD_VERSION);
+        record.extend_from_slice(&(ciphertext_length as u16).to_be_bytes());
+        record.extend_from_slice(fragment);
+        record.push(content_type.to_byte());
+        record.resize(record.len() + padding_length, 0);
+        let nonce = self.nonces.next_nonce()?;
+        let (header, inner_plaintext) = record.split_at_mut(Self::HEADER_LENGTH);
+        let tag = self.aead.encrypt_in_place_detached(&nonce, header, inner_plaintext)?;
+        record.extend_from_slice(&tag);
+        Ok(record)
+    }
+
+    /// Opens one complete TLSCiphertext record, returning the inner content
+    /// type and the fragment with its padding removed.
+    pub fn unprotect(&mut self, record: &[u8]) -> Result<(ContentType, Vec<u8>)> {
+        if record.len() < Self::HEADER_LENGTH {
+            return Err(Error::InvalidRecord);
+        }
+        let (header, encrypted_record) = record.split_at(Self::HEADER_LENGTH);
+        if header[0] != Self::OPAQUE_TYPE {
+            return Err(Error::UnexpectedMessage);
+        }
+        let length = u16::from_be_bytes([header[3], header[4]]) as usize;
+        if length != encrypted_record.len() || length < 16 {
+            return Err(Error::InvalidRecord);
+        }
+        if length > Self::MAX_CIPHERTEXT_LENGTH {
+            return Err(Error::RecordOverflow);
+        }
+        let nonce = self.nonces.next_nonce()?;
+        let (ciphertext, tag) = encrypted_record.split_at(length - 16);
+        let tag = tag.try_into().map_err(|_| Error::InvalidTagLength)?;
+        let mut inner_plaintext = ciphertext.to_vec();
+        self.aead.decrypt_in_place_detached(&nonce, header, &mut inner_plaintext, &tag)?;
+        if inner_plaintext.len() > Self::MAX_FRAGMENT_LENGTH + 1 {
+            return Err(Error::RecordOverflow);
+        }
+        while let Some(&0) = inner_plaintext.last() {
+            inner_plaintext.pop();
+        }
+        let content_type = match inner_plaintext.pop() {
+            Some(value) => ContentType::from_byte(value)?,
+            None => return Err(Error::UnexpectedMessage),
+        };
+        Ok((content_type, inner_plaintext))
+    }
+
+    /// The sequence number the next record will be protected or unprotected
+    /// with.
+    pub fn sequence_number(&self) -> u64 {
+        self.nonces.sequence_number()
+    }
+
+    /// Derives the write key and IV from a handshake or application traffic
+    /// secret (RFC 8446 section 7.3).
+    pub fn from_traffic_secret(traffic_secret: &[u8]) -> Result<RecordProtection> {
+        let key: [u8; 32] = hkdf_expand_label(traffic_secret, "key", &[], 32)?.as_slice().try_into().map_err(|_| Error::InvalidKeyLength)?;
+        let iv: [u8; 12] = hkdf_expand_label(traffic_secret, "iv", &[], 12)?.as_slice().try_into().map_err(|_| Error::InvalidNonceLength)?;
+        Ok(RecordProtection::new(key, iv))
+    }
+
+    pub fn new(key: [u8; 32], iv: [u8; 12]) -> RecordProtection {
+        RecordProtection {
+            aead: Chacha20Poly1305::new(key),
+            nonces: XorNonce::new(iv),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+
+    // RFC 8448 section 3, server handshake traffic secret. The RFC's
+    // key and IV are for AES-128-GCM, so the key is 16 bytes.
+    #[test]
+    fn hkdf_expand_label_matches_rfc_8448() {
+        let secret = hex_to_bytes("b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38");
+        assert_eq!(hkdf_expand_label(&secret, "key", &[], 16).unwrap(), hex_to_bytes("3fce516009c21727d0f2e4e86ee403bc"));
+        assert_eq!(hkdf_expand_label(&secret, "iv", &[], 12).unwrap(), hex_to_bytes("5d313eb2671276ee13000b30"));
+    }
+
+    fn pair() -> (RecordProtection, RecordProtection) {
+        let secret: [u8; 32] = [7; 32];
+        (RecordProtection::from_traffic_secret(&secret).unwrap(), RecordProtection::from_traffic_secret(&secret).unwrap())
+    }
+
+    #[test]
+    fn round_trip_with_padding() {
+        let (mut sender, mut receiver) = pair();
+        for padding_length in [0, 1, 37].iter() {
+            let record = sender.protect(ContentType::Handshake, b"hello", *padding_length).unwrap();
+            assert_eq!(record.len(), 5 + 5 + 1 + padding_length + 16);
+            assert_eq!(record[0], 23);
+            let (content_type, fragment) = receiver.unprotect(&record).unwrap();
+            assert_eq!(content_type, ContentType::Handshake);
+            assert_eq!(fragment, b"hello");
+        }
+        assert_eq!(sender.sequence_number(), 3);
+        assert_eq!(receiver.sequence_number(), 3);
+    }
+
+    #[test]
+    fn rejects_oversized_records() {
+        let (mut sender, _) = pair();
+        assert_eq!(sender.protect(ContentType::ApplicationData, b"x", usize::MAX - 5), Err(Error::RecordOverflow));
+        assert_eq!(sender.protect(ContentType::ApplicationData, &[0; (1 << 14) + 1], 0), Err(Error::RecordOverflow));
+        assert_eq!(sender.protect(ContentType::ApplicationData, &[0; 1 << 14], 1), Err(Error::RecordOverflow));
+        assert_eq!(sender.protect(ContentType::ApplicationData, b"x", 1 << 14), Err(Error::RecordOverflow));
+        assert!(sender.protect(ContentType::ApplicationData, &[0; 1 << 14], 0).is_ok());
+        assert!(sender.protect(ContentType::ApplicationData, b"x", (1 << 14) - 1).is_ok());
+        assert!(sender.protect(ContentType::ApplicationData, b"", 1 << 14).is_ok());
+    }
+
+    #[test]
+    fn rejects_wrong_type() {
+        let (mut sender, mut receiver) = pair();
+        let mut record = sender.protect(ContentType::ApplicationData, b"data", 0).unwrap();
+        record[0] = 22;
+        assert_eq!(receiver.unprotect(&record), Err(Error::UnexpectedMessage));
+    }
+
+    #[test]
+    fn rejects_bad_length() {
+        let (mut sender, mut receiver) = pair();
+        let record = sender.protect(ContentType::ApplicationData, b"data", 0).unwrap();
+        assert_eq!(receiver.unprotect(&record[..record.len() - 1]), Err(Error::InvalidRecord));
+        assert_eq!(receiver.unprotect(&record[..3]), Err(Error::InvalidRecord));
+        assert_eq!(receiver.unprotect(&[23, 3, 3, 0, 4, 1, 2, 3, 4]), Err(Error::InvalidRecord));
+    }
+
+    #[test]
+    fn rejects_tampered_tag() {
+        let (mut sender, mut receiver) = pair();
+        let mut record = sender.protect(ContentType::ApplicationData, b"data", 0).unwrap();
+        let last = record.len() - 1;
+        record[last] ^= 1;
+        assert_eq!(receiver.unprotect(&record), Err(Error::AuthenticationFailed));
+    }
+}
@@ -0,0 +1,196 @@
+use crate::crypto::utils::constant_time_eq;
+use crate::crypto::utils::u256::U256;
+
+use core::ops::{Add, Sub, Mul, Neg};
+
+/// An element of GF(2^255 - 19), the field under Curve25519 and Ed25519.
+///
+/// Values are kept in `[0, 2^256)` and only fully reduced when encoded, using
+/// `2^256 = 38 (mod p)` to fold products back into 256 bits. Every operation
+/// uses the constant-time `U256` primitives, so none of them branch on or
+/// index by the value.
+#[derive(Debug, Copy, Clone)]
+pub struct FieldElement(U256);
+
+impl FieldElement {
+    fn prime() -> U256 {
+        U256::from_limbs([0xFFFFFFFFFFFFFFED, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0x7FFFFFFFFFFFFFFF])
+    }
+
+    fn small(value: u64) -> U256 {
+        U256::from_limbs([value, 0, 0, 0])
+    }
+
+    // Adds `carry * 38`, standing in for `carry * 2^256`, twice: the second
+    // addition can only carry when the first left a value below 38 * carry.
+    fn fold_carry(value: U256, carry: u64) -> U256 {
+        let (value, carry) = value.overflowing_add(Self::small(carry * 38));
+        let (value, _) = value.overflowing_add(Self::small(carry as u64 * 38));
+        return value;
+    }
+
+    fn reduce_wide(low: U256, high: U256) -> FieldElement {
+        let (high_low, high_high) = high.widening_mul(Self::small(38));
+        let (sum, carry) = low.overflowing_add(high_low);
+        FieldElement(Self::fold_carry(sum, high_high.to_limbs()[0] + carry as u64))
+    }
+
+    // Subtracts p once if that does not underflow.
+    fn subtract_prime(value: U256) -> U256 {
+        let (difference, borrow) = value.overflowing_sub(Self::prime());
+        U256::conditional_select(difference, value, borrow as u8)
+    }
+
+    pub fn square(self) -> FieldElement {
+        self * self
+    }
+
+    // Raises to a public exponent by square-and-multiply over its bits.
+    pub fn pow(self, exponent: U256) -> FieldElement {
+        let exponent_bytes = exponent.to_le_bytes();
+        let mut result = FieldElement::one();
+        for i in (0..256).rev() {
+            result = result.square();
+            if (exponent_bytes[i / 8] >> (i % 8)) & 1 == 1 {
+                result = result * self;
+            }
+        }
+        return result;
+    }
+
+    /// The multiplicative inverse by Fermat's little theorem; zero maps to zero.
+    pub fn invert(self) -> FieldElement {
+        let (exponent, _) = Self::prime().overflowing_sub(Self::small(2));
+        self.pow(exponent)
+    }
+
+    pub fn conditional_swap(first: &mut FieldElement, second: &mut FieldElement, choice: u8) {
+        U256::conditional_swap(&mut first.0, &mut second.0, choice);
+    }
+
+    pub fn conditional_select(first: FieldElement, second: FieldElement, choice: u8) -> FieldElement {
+        FieldElement(U256::conditional_select(first.0, second.0, choice))
+    }
+
+    /// Reads 32 little-endian bytes, ignoring the top bit as RFC 7748
+    /// requires for u-coordinates.
+    pub fn from_bytes(value: [u8; 32]) -> FieldElement {
+        let mut value_bytes = value;
+        value_bytes[31] &= 0x7F;
+        FieldElement(U256::from_le_bytes(value_bytes))
+    }
+
+    /// The canonical little-endian encoding, fully reduced below p.
+    pub fn to_bytes(self) -> [u8; 32] {
+        Self::subtract_prime(Self::subtract_prime(self.0)).to_le_bytes()
+    }
+
+    /// Whether the canonical value is odd, the "sign" RFC 8032 encodes.
+    pub fn is_negative(self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    pub fn from_limbs(limbs: [u64; 4]) -> FieldElement {
+        FieldElement(U256::from_limbs(limbs))
+    }
+
+    pub fn from_u64(value: u64) -> FieldElement {
+        FieldElement(Self::small(value))
+    }
+
+    pub fn zero() -> FieldElement {
+        FieldElement(U256::zero())
+    }
+
+    pub fn one() -> FieldElement {
+        FieldElement(U256::one())
+    }
+}
+
+impl Add for FieldElement {
+    type Output = Self;
+    fn add(self, value: FieldElement) -> Self::Output {
+        let (sum, carry) = self.0.overflowing_add(value.0);
+        FieldElement(Self::fold_carry(sum, carry as u64))
+    }
+}
+
+impl Sub for FieldElement {
+    type Output = Self;
+    fn sub(self, value: FieldElement) -> Self::Output {
+        let (difference, borrow) = self.0.overflowing_sub(value.0);
+        let (difference, borrow) = difference.overflowing_sub(Self::small(borrow as u64 * 38));
+        let (difference, _) = difference.overflowing_sub(Self::small(borrow as u64 * 38));
+        FieldElement(difference)
+    }
+}
+
+impl Mul for FieldElement {
+    type Output = Self;
+    fn mul(self, value: FieldElement) -> Self::Output {
+        let (low, high) = self.0.widening_mul(value.0);
+        Self::reduce_wide(low, high)
+    }
+}
+
+impl Neg for FieldElement {
+    type Output = Self;
+    fn neg(self) -> FieldElement {
+        FieldElement::zero() - self
+    }
+}
+
+impl PartialEq for FieldElement {
+    fn eq(&self, other: &FieldElement) -> bool {
+        constant_time_eq(&self.to_bytes(), &other.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding_is_canonical() {
+        // p itself and the largest 255-bit value both reduce.
+        let mut p_bytes: [u8; 32] = [0xff; 32];
+        p_bytes[0] = 0xed;
+        p_bytes[31] = 0x7f;
+        assert_eq!(FieldElement::from_bytes(p_bytes).to_bytes(), [0; 32]);
+        let mut expected: [u8; 32] = [0; 32];
+        expected[0] = 18;
+        assert_eq!(FieldElement::from_bytes([0xff; 32]).to_bytes(), expected);
+        assert!(FieldElement::from_bytes(p_bytes) == FieldElement::zero());
+    }
+
+    #[test]
+    fn arithmetic() {
+        let one = FieldElement::one();
+        let minus_one = FieldElement::zero() - one;
+        let mut expected: [u8; 32] = [0xff; 32];
+        expected[0] = 0xec;
+        expected[31] = 0x7f;
+        assert_eq!(minus_one.to_bytes(), expected);
+        assert!(minus_one + one == FieldElement::zero());
+        assert!(-minus_one == one);
+        assert!(minus_one * minus_one == one);
+        assert!(FieldElement::from_u64(121665) * FieldElement::from_u64(2) == FieldElement::from_u64(243330));
+
+        let value = FieldElement::from_limbs([0x0123456789abcdef, 0xfedcba9876543210, 0x1111111111111111, 0x7777777777777777]);
+        assert!(value * value.invert() == one);
+        assert!(value.square() == value * value);
+        assert!(FieldElement::zero().invert() == FieldElement::zero());
+    }
+
+    #[test]
+    fn conditional_select() {
+        let first = FieldElement::from_u64(1);
+        let second = FieldElement::from_u64(2);
+        assert!(FieldElement::conditional_select(first, second, 0) == first);
+        assert!(FieldElement::conditional_select(first, second, 1) == second);
+        let mut a = first;
+        let mut b = second;
+        FieldElement::conditional_swap(&mut a, &mut b, 1);
+        assert!(a == second && b == first);
+    }
+}
@@ -1,7 +1,6 @@
+pub mod field25519;
 pub mod u256;
 
-use self::u256::U256;
-
 pub fn bytes_to_word(byte_array: &[u8]) -> u32 {
     let mut return_value: u32 = 0;
     for i in (0..4).rev() {
@@ -17,4 +16,28 @@ pub fn word_to_bytes(word: u32) -> [u8; 4] {
         ((word >> 16) & 255) as u8,
         ((word >> 24) & 255) as u8
     ]
+}
+
+// Compares two byte strings without branching on their contents.
+pub fn constant_time_eq(first: &[u8], second: &[u8]) -> bool {
+    if first.len() != second.len() {
+        return false;
+    }
+    let mut difference: u8 = 0;
+    for i in 0..first.len() {
+        difference |= first[i] ^ second[i];
+    }
+    difference == 0
+}
+
+// Decodes a hex test vector, ignoring whitespace.
+#[cfg(test)]
+pub(crate) fn hex_to_bytes(hex: &str) -> Vec<u8> {
+    let digits: Vec<u8> = hex.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    let mut bytes: Vec<u8> = Vec::new();
+    for pair in digits.chunks(2) {
+        let pair = core::str::from_utf8(pair).unwrap();
+        bytes.push(u8::from_str_radix(pair, 16).unwrap());
+    }
+    return bytes;
 }
\ No newline at end of file
@@ -1,4 +1,6 @@
-use core::convert::{From, Into};
+use crate::error::{Error, Result};
+
+use core::convert::{From, TryFrom};
 use core::ops::{Add, Sub, Not, Mul, Div, Rem, Shr, Shl, BitAnd, BitOr, BitXor};
 use core::cmp::Ordering;
 
@@ -11,13 +13,16 @@ impl U256 {
         arr.iter().fold(String::new(), |acc, &x| acc + &format!("{:02x}", &x))
     }
 
-    pub fn from_hex_string(value: &str) -> U256 {
+    pub fn from_hex_string(value: &str) -> Result<U256> {
+        if value.len() > 64 || !value.bytes().all(|x| x.is_ascii_hexdigit()) {
+            return Err(Error::InvalidHexString);
+        }
         let mut value_array: [u8; 32] = [0; 32];
         let formatted_value = &format!("{:0>64}", value);
         for i in (0..formatted_value.len()).step_by(2) {
-            value_array[i / 2] = u8::from_str_radix(&formatted_value[i..i+2], 16).unwrap();
+            value_array[i / 2] = u8::from_str_radix(&formatted_value[i..i+2], 16).map_err(|_| Error::InvalidHexString)?;
         }
-        U256(value_array)
+        Ok(U256(value_array))
     }
 
     pub fn to_byte_array(self) -> [u8; 32] {
@@ -33,7 +38,7 @@ impl U256 {
         U256(value_array)
     }
 
-    pub fn from_message_block(message_block: std::vec::Drain<'_, u8>) -> U256 {
+    pub fn from_message_block<I: IntoIterator<Item = u8>>(message_block: I) -> U256 {
         let mut index: usize = 31;
         let mut byte_array: [u8; 32] = [0; 32];
         for value in message_block {
@@ -44,6 +49,124 @@ impl U256 {
         U256(byte_array)
     }
 
+    pub fn checked_div(self, divisor: U256) -> Result<U256> {
+        if divisor == U256::zero() {
+            return Err(Error::DivisionByZero);
+        }
+        Ok(self / divisor)
+    }
+
+    pub fn checked_rem(self, divisor: U256) -> Result<U256> {
+        if divisor == U256::zero() {
+            return Err(Error::DivisionByZero);
+        }
+        Ok(self % divisor)
+    }
+
+    pub fn from_le_bytes(value: [u8; 32]) -> U256 {
+        let mut value_array: [u8; 32] = [0; 32];
+        for i in 0..32 {
+            value_array[i] = value[31 - i];
+        }
+        U256(value_array)
+    }
+
+    pub fn to_le_bytes(self) -> [u8; 32] {
+        let mut return_value: [u8; 32] = [0; 32];
+        for i in 0..32 {
+            return_value[i] = self.0[31 - i];
+        }
+        return return_value;
+    }
+
+    // Little-endian 64-bit limbs, for the constant-time arithmetic below.
+    pub fn to_limbs(self) -> [u64; 4] {
+        let mut limbs: [u64; 4] = [0; 4];
+        for i in 0..4 {
+            let mut limb_bytes: [u8; 8] = [0; 8];
+            limb_bytes.copy_from_slice(&self.0[(24 - i * 8)..(32 - i * 8)]);
+            limbs[i] = u64::from_be_bytes(limb_bytes);
+        }
+        return limbs;
+    }
+
+    pub fn from_limbs(limbs: [u64; 4]) -> U256 {
+        let mut value_array: [u8; 32] = [0; 32];
+        for i in 0..4 {
+            value_array[(24 - i * 8)..(32 - i * 8)].copy_from_slice(&limbs[i].to_be_bytes());
+        }
+        U256(value_array)
+    }
+
+    // Unlike `+`, `-` and `*`, the following run in time independent of
+    // their operands, so they are safe to use on secret values.
+    pub fn overflowing_add(self, value: U256) -> (U256, bool) {
+        let first = self.to_limbs();
+        let second = value.to_limbs();
+        let mut result: [u64; 4] = [0; 4];
+        let mut carry: u64 = 0;
+        for i in 0..4 {
+            let sum = (first[i] as u128) + (second[i] as u128) + (carry as u128);
+            result[i] = sum as u64;
+            carry = (sum >> 64) as u64;
+        }
+        (U256::from_limbs(result), carry != 0)
+    }
+
+    pub fn overflowing_sub(self, value: U256) -> (U256, bool) {
+        let first = self.to_limbs();
+        let second = value.to_limbs();
+        let mut result: [u64; 4] = [0; 4];
+        let mut borrow: u64 = 0;
+        for i in 0..4 {
+            let difference = (first[i] as u128).wrapping_sub(second[i] as u128).wrapping_sub(borrow as u128);
+            result[i] = difference as u64;
+            borrow = ((difference >> 64) as u64) & 1;
+        }
+        (U256::from_limbs(result), borrow != 0)
+    }
+
+    /// The full 512-bit product, as `(low, high)` halves.
+    pub fn widening_mul(self, value: U256) -> (U256, U256) {
+        let first = self.to_limbs();
+        let second = value.to_limbs();
+        let mut product: [u64; 8] = [0; 8];
+        for i in 0..4 {
+            let mut carry: u64 = 0;
+            for j in 0..4 {
+                let term = (first[i] as u128) * (second[j] as u128) + (product[i + j] as u128) + (carry as u128);
+                product[i + j] = term as u64;
+                carry = (term >> 64) as u64;
+            }
+            product[i + 4] = carry;
+        }
+        let mut low: [u64; 4] = [0; 4];
+        let mut high: [u64; 4] = [0; 4];
+        low.copy_from_slice(&product[0..4]);
+        high.copy_from_slice(&product[4..8]);
+        (U256::from_limbs(low), U256::from_limbs(high))
+    }
+
+    /// Returns `second` if `choice` is 1 and `first` if it is 0.
+    pub fn conditional_select(first: U256, second: U256, choice: u8) -> U256 {
+        let mask = 0u8.wrapping_sub(choice);
+        let mut result_array: [u8; 32] = [0; 32];
+        for i in 0..32 {
+            result_array[i] = first.0[i] ^ (mask & (first.0[i] ^ second.0[i]));
+        }
+        U256(result_array)
+    }
+
+    /// Swaps `first` and `second` if `choice` is 1 and leaves them if it is 0.
+    pub fn conditional_swap(first: &mut U256, second: &mut U256, choice: u8) {
+        let mask = 0u8.wrapping_sub(choice);
+        for i in 0..32 {
+            let difference = mask & (first.0[i] ^ second.0[i]);
+            first.0[i] ^= difference;
+            second.0[i] ^= difference;
+        }
+    }
+
     pub fn zero() -> U256 {
         U256([0; 32])
     }
@@ -89,12 +212,15 @@ impl From<u8> for U256 {
     }
 }
 
-impl Into<u8> for U256 {
-    fn into(self) -> u8 {
-        for b in &self.0[0..31] {
-            assert_eq!(*b, 0);
+impl TryFrom<U256> for u8 {
+    type Error = Error;
+    fn try_from(val: U256) -> Result<u8> {
+        for b in &val.0[0..31] {
+            if *b != 0 {
+                return Err(Error::Overflow);
+            }
         }
-        self.0[31]
+        Ok(val.0[31])
     }
 }
 
@@ -110,24 +236,27 @@ impl From<usize> for U256 {
     }
 }
 
-impl Into<usize> for U256 {
-    fn into(self) -> usize {
+impl TryFrom<U256> for usize {
+    type Error = Error;
+    fn try_from(val: U256) -> Result<usize> {
         let mut value_bytes = 0usize.to_be_bytes();
         let offset = 32 - value_bytes.len();
-        for b in &self.0[0..offset] {
-            assert_eq!(*b, 0);
+        for b in &val.0[0..offset] {
+            if *b != 0 {
+                return Err(Error::Overflow);
+            }
         }
         for (i, v) in value_bytes.iter_mut().enumerate() {
-            *v = self.0[i + offset];
+            *v = val.0[i + offset];
         }
-        usize::from_be_bytes(value_bytes)
+        Ok(usize::from_be_bytes(value_bytes))
     }
 }
 
 impl Ord for U256 {
     fn cmp(&self, other: &U256) -> Ordering {
-        let &U256(ref first) = self;
-        let &U256(ref second) = other;
+        let U256(first) = self;
+        let U256(second) = other;
         let mut first_is_larger: u8 = 0;
         let mut second_is_larger: u8 = 0;
         let possible_results: [Ordering; 3] = [
@@ -136,10 +265,10 @@ impl Ord for U256 {
             Ordering::Less
         ];
         for i in 0..32 {
-            let first_byte_is_larger = first[i].overflowing_sub(second[i]).1 as u8;
-            let second_byte_is_larger = second[i].overflowing_sub(first[i]).1 as u8;
-            first_is_larger |= (first_byte_is_larger & !second_is_larger);
-            second_is_larger |= (second_byte_is_larger & !first_is_larger);
+            let first_byte_is_larger = second[i].overflowing_sub(first[i]).1 as u8;
+            let second_byte_is_larger = first[i].overflowing_sub(second[i]).1 as u8;
+            first_is_larger |= first_byte_is_larger & !second_is_larger;
+            second_is_larger |= second_byte_is_larger & !first_is_larger;
         }
         possible_results[((second_is_larger << 1) + first_is_larger) as usize]
     }
@@ -193,31 +322,17 @@ impl BitXor for U256 {
 impl Shl for U256 {
     type Output = Self;
     fn shl(self, value: U256) -> Self::Output {
-        let value_u8: u8 = value.into();
-        let mut partial_mask: u8 = 0;
-        let mut masks: [u8; 32] = [0; 32];
+        let value_u8 = match u8::try_from(value) {
+            Ok(shift) => shift,
+            Err(_) => return U256::zero(),
+        };
+        let byte_shift = (value_u8 / 8u8) as usize;
+        let bit_shift = (value_u8 % 8u8) as u32;
         let mut result_array: [u8; 32] = [0; 32];
-        for i in 0..32 {
-            result_array[i] = self.0[i];
-        }
-        let full_mask_count: u8 = value_u8 / 8u8;
-        let partial_mask_bits: u8 = value_u8 % 8u8;
-        for i in 0..full_mask_count {
-            masks[i as usize] = 0xFF;
-        }
-        for i in 0..partial_mask_bits {
-            partial_mask |= (0x01 << (7 - i))
-        }
-        masks[full_mask_count as usize] = partial_mask;
-        for index in 0..32 {
-            let mask = masks[index];
-            let shift_count = mask.count_ones();
-            for i in 0..32 {
-                let carry_value: u8 = result_array[i] & mask;
-                if i > 0 {
-                    result_array[i - 1] |= carry_value;
-                }
-                result_array[i] = result_array[i].overflowing_shl(shift_count).0;
+        for i in 0..(32 - byte_shift) {
+            result_array[i] = self.0[i + byte_shift] << bit_shift;
+            if bit_shift > 0 && i + byte_shift < 31 {
+                result_array[i] |= self.0[i + byte_shift + 1] >> (8 - bit_shift);
             }
         }
         U256(result_array)
@@ -227,31 +342,17 @@ impl Shl for U256 {
 impl Shr for U256 {
     type Output = Self;
     fn shr(self, value: U256) -> Self::Output {
-        let value_u8: u8 = value.into();
-        let mut partial_mask: u8 = 0;
-        let mut masks: [u8; 32] = [0; 32];
+        let value_u8 = match u8::try_from(value) {
+            Ok(shift) => shift,
+            Err(_) => return U256::zero(),
+        };
+        let byte_shift = (value_u8 / 8u8) as usize;
+        let bit_shift = (value_u8 % 8u8) as u32;
         let mut result_array: [u8; 32] = [0; 32];
-        for i in 0..32 {
-            result_array[i] = self.0[i];
-        }
-        let full_mask_count: u8 = value_u8 / 8u8;
-        let partial_mask_bits: u8 = value_u8 % 8u8;
-        for i in 0..full_mask_count {
-            masks[i as usize] = 0xFF;
-        }
-        for i in 0..partial_mask_bits {
-            partial_mask |= (0x01 << i)
-        }
-        masks[full_mask_count as usize] = partial_mask;
-        for index in 0..32 {
-            let mask = masks[index];
-            let shift_count = mask.count_ones();
-            for i in (0..32).rev() {
-                let carry_value = result_array[i] & mask;
-                if i < 31 {
-                    result_array[i + 1] |= carry_value;
-                }
-                result_array[i] = result_array[i] >> shift_count;
+        for i in byte_shift..32 {
+            result_array[i] = self.0[i - byte_shift] >> bit_shift;
+            if bit_shift > 0 && i > byte_shift {
+                result_array[i] |= self.0[i - byte_shift - 1] << (8 - bit_shift);
             }
         }
         U256(result_array)
@@ -309,7 +410,9 @@ impl Mul for U256 {
 impl Div for U256 {
     type Output = Self;
     fn div(self, divisor: U256) -> Self::Output {
-        if self == divisor {
+        if divisor == U256::zero() {
+            panic!("attempt to divide by zero");
+        } else if self == divisor {
             return U256::one();
         } else if self < divisor {
             return U256::zero();
@@ -319,7 +422,7 @@ impl Div for U256 {
         for i in (0..256).rev() {
             let shift = U256::from(i as usize);
             let r = divisor << shift;
-            if r <= dividend {
+            if (r >> shift) == divisor && r <= dividend {
                 quotient = quotient | (U256::one() << shift);
                 dividend = dividend - r;
             }
@@ -337,3 +440,114 @@ impl Rem for U256 {
         self - (divisor * (self / divisor))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(value: &str) -> U256 {
+        U256::from_hex_string(value).unwrap()
+    }
+
+    #[test]
+    fn comparison_is_decided_by_the_highest_difference() {
+        let high = U256::from_limbs([0, 0, 0, 1]);
+        let low = U256::from_limbs([u64::MAX, u64::MAX, u64::MAX, 0]);
+        assert_eq!(high.cmp(&low), Ordering::Greater);
+        assert_eq!(low.cmp(&high), Ordering::Less);
+        assert!(low < high);
+        assert_eq!(high.cmp(&high), Ordering::Equal);
+        assert!(U256::from(2u8) > U256::one());
+        assert!(U256::zero() < U256::max());
+    }
+
+    #[test]
+    fn shifts() {
+        let value = hex("8000000000000000fedcba98765432100123456789abcdef00000000000000ff");
+        assert_eq!(value << U256::zero(), value);
+        assert_eq!(value >> U256::zero(), value);
+        assert_eq!(value << U256::from(64u8), hex("fedcba98765432100123456789abcdef00000000000000ff0000000000000000"));
+        assert_eq!(value >> U256::from(64u8), hex("8000000000000000fedcba98765432100123456789abcdef"));
+        assert_eq!(value << U256::from(255u8), hex("8000000000000000000000000000000000000000000000000000000000000000"));
+        assert_eq!(value >> U256::from(255u8), U256::one());
+        assert_eq!(value << U256::from(4u8), hex("000000000000000fedcba98765432100123456789abcdef00000000000000ff0"));
+        assert_eq!(value >> U256::from(4u8), hex("08000000000000000fedcba98765432100123456789abcdef00000000000000f"));
+        assert_eq!(U256::one() << U256::from(64u8), U256::from_limbs([0, 1, 0, 0]));
+        assert_eq!(U256::max() << U256::from(256usize), U256::zero());
+        assert_eq!(U256::max() >> U256::from(256usize), U256::zero());
+    }
+
+    #[test]
+    fn division() {
+        let value = hex("123456789abcdef0fedcba987654321000112233445566778899aabbccddeeff");
+        assert_eq!(value / U256::one(), value);
+        assert_eq!(value % U256::one(), U256::zero());
+        assert_eq!(value / value, U256::one());
+        assert_eq!(U256::one() / value, U256::zero());
+        let divisor = hex("fedcba9876543210");
+        let quotient = value / divisor;
+        let remainder = value % divisor;
+        assert!(remainder < divisor);
+        assert_eq!(quotient * divisor + remainder, value);
+        assert_eq!(U256::max() / U256::max(), U256::one());
+        assert_eq!(U256::max() / U256::from(2u8), U256::max() >> U256::one());
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(U256::one().checked_div(U256::zero()), Err(Error::DivisionByZero));
+        assert_eq!(U256::one().checked_rem(U256::zero()), Err(Error::DivisionByZero));
+        assert_eq!(U256::from(7u8).checked_div(U256::from(2u8)), Ok(U256::from(3u8)));
+        assert_eq!(U256::from(7u8).checked_rem(U256::from(2u8)), Ok(U256::one()));
+    }
+
+    #[test]
+    #[should_panic(expected = "attempt to divide by zero")]
+    fn division_operator_panics_on_zero() {
+        let _ = U256::one() / U256::zero();
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(u8::try_from(U256::from(255u8)), Ok(255));
+        assert_eq!(u8::try_from(U256::from(256usize)), Err(Error::Overflow));
+        assert_eq!(usize::try_from(U256::from(usize::MAX)), Ok(usize::MAX));
+        assert_eq!(usize::try_from(U256::from_limbs([0, 0, 1, 0])), Err(Error::Overflow));
+        assert_eq!(U256::from(0x01020304u32), hex("1020304"));
+        assert_eq!(hex("ff").to_hex_string(), format!("{:0>64}", "ff"));
+        assert_eq!(U256::from_hex_string("xyz"), Err(Error::InvalidHexString));
+        assert_eq!(U256::from_hex_string(&"f".repeat(65)), Err(Error::InvalidHexString));
+
+        let limbs: [u64; 4] = [1, 2, 3, 0x8000000000000000];
+        let value = U256::from_limbs(limbs);
+        assert_eq!(value.to_limbs(), limbs);
+        assert_eq!(U256::from_le_bytes(value.to_le_bytes()), value);
+        assert_eq!(value.to_le_bytes()[0], 1);
+        assert_eq!(value.to_le_bytes()[31], 0x80);
+    }
+
+    #[test]
+    fn limb_arithmetic() {
+        assert_eq!(U256::max().overflowing_add(U256::one()), (U256::zero(), true));
+        assert_eq!(U256::max().overflowing_add(U256::zero()), (U256::max(), false));
+        assert_eq!(U256::zero().overflowing_sub(U256::one()), (U256::max(), true));
+        assert_eq!(U256::from_limbs([0, 1, 0, 0]).overflowing_sub(U256::one()), (U256::from_limbs([u64::MAX, 0, 0, 0]), false));
+        // (2^256 - 1)^2 = 2^256 * (2^256 - 2) + 1
+        let (low, high) = U256::max().widening_mul(U256::max());
+        assert_eq!(low, U256::one());
+        assert_eq!(high, U256::max() - U256::one());
+        let (low, high) = U256::from_limbs([0, 0, 0, 1]).widening_mul(U256::from_limbs([0, 0, 0, 1]));
+        assert_eq!(low, U256::zero());
+        assert_eq!(high, U256::from_limbs([0, 0, 1, 0]));
+
+        let first = U256::from(1u8);
+        let second = U256::from(2u8);
+        assert_eq!(U256::conditional_select(first, second, 0), first);
+        assert_eq!(U256::conditional_select(first, second, 1), second);
+        let (mut a, mut b) = (first, second);
+        U256::conditional_swap(&mut a, &mut b, 0);
+        assert_eq!((a, b), (first, second));
+        U256::conditional_swap(&mut a, &mut b, 1);
+        assert_eq!((a, b), (second, first));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::crypto::chacha20poly1305::{Chacha20Poly1305, Tag};
+use crate::error::{Error, Result};
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+pub const MESSAGE_TYPE_TRANSPORT_DATA: u8 = 4;
+/// Type byte, three reserved zero bytes, receiver index and counter.
+pub const HEADER_LENGTH: usize = 16;
+/// Counters at or past this value are never sent or accepted.
+pub const REJECT_AFTER_MESSAGES: u64 = u64::MAX - (1 << 13);
+
+fn transport_nonce(counter: u64) -> [u8; 12] {
+    let mut nonce: [u8; 12] = [0; 12];
+    nonce[4..12].copy_from_slice(&counter.to_le_bytes());
+    return nonce;
+}
+
+/// Reads the receiver index and counter from a transport data message header
+/// so the caller can pick the matching session before opening it.
+pub fn parse_header(message: &[u8]) -> Result<(u32, u64)> {
+    if message.len() < HEADER_LENGTH + 16 {
+        return Err(Error::InvalidPacketLength);
+    }
+    if message[0] != MESSAGE_TYPE_TRANSPORT_DATA || message[1..4] != [0, 0, 0] {
+        return Err(Error::UnexpectedMessage);
+    }
+    let receiver_index = u32::from_le_bytes(message[4..8].try_into().map_err(|_| Error::InvalidPacketLength)?);
+    let counter = u64::from_le_bytes(message[8..16].try_into().map_err(|_| Error::InvalidPacketLength)?);
+    Ok((receiver_index, counter))
+}
+
+/// Sealing half of a WireGuard session: builds transport data messages
+/// (`4 || 0 0 0 || le32(receiver_index) || le64(counter) || ciphertext || tag`).
+///
+/// The plaintext is zero padded to a multiple of 16 bytes and sealed with
+/// empty AAD under the nonce `0 0 0 0 || le64(counter)`.
+pub struct TransportSender {
+    aead: Chacha20Poly1305,
+    receiver_index: u32,
+    counter: u64,
+}
+
+impl TransportSender {
+    pub fn seal(&mut self, packet: &[u8]) -> Result<Vec<u8>> {
+        if self.counter >= REJECT_AFTER_MESSAGES {
+            return Err(Error::NonceExhausted);
+        }
+        let padded_length = packet.len().div_ceil(16) * 16;
+        let mut message: Vec<u8> = Vec::new();
+        message.push(MESSAGE_TYPE_TRANSPORT_DATA);
+        message.extend_from_slice(&[0, 0, 0]);
+        message.extend_from_slice(&self.receiver_index.to_le_bytes());
+        message.extend_from_slice(&self.counter.to_le_bytes());
+        message.extend_from_slice(packet);
+        message.resize(HEADER_LENGTH + padded_length, 0);
+        let tag = self.aead.encrypt_in_place_detached(&transport_nonce(self.counter), &[], &mut message[HEADER_LENGTH..])?;
+        message.extend_from_slice(&tag);
+        self.counter += 1;
+        Ok(message)
+    }
+
+    /// The counter the next message will be sent with.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    pub fn new(key: [u8; 32], receiver_index: u32) -> TransportSender {
+        TransportSender {
+            aead: Chacha20Poly1305::new(key),
+            receiver_index: receiver_index,
+            counter: 0,
+        }
+    }
+}
+
+/// Opening half of a WireGuard session.
+///
+/// Counters are checked against a sliding replay window before decrypting,
+/// and only recorded once the message has authenticated, so forged messages
+/// cannot move the window. The returned plaintext still carries its zero
+/// padding; the inner IP header says how much of it is the packet.
+pub struct TransportReceiver {
+    aead: Chacha20Poly1305,
+    local_index: u32,
+    window: ReplayWindow,
+}
+
+impl TransportReceiver {
+    pub fn open(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+        let (receiver_index, counter) = parse_header(message)?;
+        if receiver_index != self.local_index {
+            return Err(Error::UnexpectedMessage);
+        }
+        if !self.window.check(counter) {
+            return Err(Error::ReplayedCounter);
+        }
+        let (ciphertext, tag) = message[HEADER_LENGTH..].split_at(message.len() - HEADER_LENGTH - 16);
+        let tag: Tag = tag.try_into().map_err(|_| Error::InvalidTagLength)?;
+        let mut plaintext = ciphertext.to_vec();
+        self.aead.decrypt_in_place_detached(&transport_nonce(counter), &[], &mut plaintext, &tag)?;
+        if !self.window.update(counter) {
+            return Err(Error::ReplayedCounter);
+        }
+        Ok(plaintext)
+    }
+
+    pub fn new(key: [u8; 32], local_index: u32) -> TransportReceiver {
+        TransportReceiver {
+            aead: Chacha20Poly1305::new(key),
+            local_index: local_index,
+            window: ReplayWindow::new(),
+        }
+    }
+}
+
+/// The RFC 6479 style anti-replay bitmap WireGuard uses: a ring of 64-bit
+/// blocks covering the `WINDOW_SIZE` counters below the highest one seen.
+/// Blocks are cleared as the window slides forward, so advancing costs at
+/// most one pass over the ring however far the counter jumps.
+pub struct ReplayWindow {
+    last_counter: u64,
+    blocks: [u64; ReplayWindow::RING_BLOCKS],
+}
+
+impl ReplayWindow {
+    const RING_BLOCKS: usize = 128;
+    pub const WINDOW_SIZE: u64 = (Self::RING_BLOCKS as u64 - 1) * 64;
+
+    /// Whether `counter` would be accepted, without recording it.
+    pub fn check(&self, counter: u64) -> bool {
+        if counter >= REJECT_AFTER_MESSAGES {
+            return false;
+        }
+        if counter > self.last_counter {
+            return true;
+        }
+        if self.last_counter - counter > Self::WINDOW_SIZE {
+            return false;
+        }
+        let block = self.blocks[((counter >> 6) as usize) % Self::RING_BLOCKS];
+        return (block >> (counter & 63)) & 1 == 0;
+    }
+
+    /// Records `counter`, returning false if it is a replay or too old.
+    pub fn update(&mut self, counter: u64) -> bool {
+        if !self.check(counter) {
+            return false;
+        }
+        let block_index = counter >> 6;
+        if counter > self.last_counter {
+            let current_index = self.last_counter >> 6;
+            let mut blocks_to_clear = block_index - current_index;
+            if blocks_to_clear > Self::RING_BLOCKS as u64 {
+                blocks_to_clear = Self::RING_BLOCKS as u64;
+            }
+            for i in 1..=blocks_to_clear {
+                self.blocks[((current_index + i) as usize) % Self::RING_BLOCKS] = 0;
+            }
+            self.last_counter = counter;
+        }
+        self.blocks[(block_index as usize) % Self::RING_BLOCKS] |= 1 << (counter & 63);
+        return true;
+    }
+
+    pub fn new() -> ReplayWindow {
+        ReplayWindow {
+            last_counter: 0,
+            blocks: [0; Self::RING_BLOCKS],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_duplicates() {
+        let mut window = ReplayWindow::new();
+        assert!(window.update(0));
+        assert!(!window.update(0));
+        assert!(window.update(5));
+        assert!(!window.check(5));
+        assert!(!window.update(5));
+    }
+
+    #[test]
+    fn accepts_reordering_within_the_window() {
+        let mut window = ReplayWindow::new();
+        assert!(window.update(100));
+        for counter in (0..100).rev() {
+            assert!(window.update(counter));
+        }
+        for counter in 0..=100 {
+            assert!(!window.check(counter));
+        }
+        assert!(window.update(101));
+    }
+
+    #[test]
+    fn window_edge() {
+        let last = 20000;
+        let mut window = ReplayWindow::new();
+        assert!(window.update(last));
+        assert!(!window.check(last - ReplayWindow::WINDOW_SIZE - 1));
+        assert!(!window.update(last - 8129));
+        assert!(window.check(last - ReplayWindow::WINDOW_SIZE));
+        assert!(window.update(last - 8128));
+        assert!(!window.update(last - 8128));
+    }
+
+    #[test]
+    fn large_jumps_clear_the_ring() {
+        let mut window = ReplayWindow::new();
+        for counter in 0..200 {
+            assert!(window.update(counter));
+        }
+        let jump = 1 << 40;
+        assert!(window.update(jump));
+        assert!(!window.check(199));
+        // Counters in the window below the jump land in cleared blocks.
+        assert!(window.update(jump - 1));
+        assert!(window.update(jump - 64 * 100));
+        assert!(!window.update(jump - 64 * 100));
+        assert!(window.update(jump + 64 * 3));
+        assert!(window.update(jump + 1));
+    }
+
+    #[test]
+    fn round_trip_and_replay() {
+        let key: [u8; 32] = [9; 32];
+        let mut sender = TransportSender::new(key, 77);
+        let mut receiver = TransportReceiver::new(key, 77);
+        let first = sender.seal(b"first packet").unwrap();
+        let second = sender.seal(b"second").unwrap();
+        assert_eq!(first.len(), HEADER_LENGTH + 16 + 16);
+        assert_eq!(parse_header(&second).unwrap(), (77, 1));
+        assert_eq!(&receiver.open(&second).unwrap()[..6], b"second");
+        assert_eq!(&receiver.open(&first).unwrap()[..12], b"first packet");
+        assert_eq!(receiver.open(&first), Err(Error::ReplayedCounter));
+    }
+
+    #[test]
+    fn forged_messages_do_not_move_the_window() {
+        let key: [u8; 32] = [9; 32];
+        let mut sender = TransportSender::new(key, 1);
+        let mut receiver = TransportReceiver::new(key, 1);
+        let mut message = sender.seal(b"data").unwrap();
+        let last = message.len() - 1;
+        message[last] ^= 1;
+        assert_eq!(receiver.open(&message), Err(Error::AuthenticationFailed));
+        message[last] ^= 1;
+        assert!(receiver.open(&message).is_ok());
+    }
+
+    #[test]
+    fn reject_after_messages() {
+        let key: [u8; 32] = [9; 32];
+        let mut sender = TransportSender::new(key, 1);
+        let mut receiver = TransportReceiver::new(key, 1);
+        sender.counter = REJECT_AFTER_MESSAGES - 1;
+        let last_message = sender.seal(b"last").unwrap();
+        assert_eq!(sender.counter(), REJECT_AFTER_MESSAGES);
+        assert_eq!(sender.seal(b"one more"), Err(Error::NonceExhausted));
+
+        assert!(receiver.open(&last_message).is_ok());
+        let mut too_far = last_message.clone();
+        too_far[8..16].copy_from_slice(&REJECT_AFTER_MESSAGES.to_le_bytes());
+        assert_eq!(receiver.open(&too_far), Err(Error::ReplayedCounter));
+
+        let mut window = ReplayWindow::new();
+        assert!(!window.check(REJECT_AFTER_MESSAGES));
+        assert!(!window.update(u64::MAX));
+        assert!(window.update(REJECT_AFTER_MESSAGES - 1));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::crypto::chacha20poly1305::Chacha20Poly1305;
+use crate::crypto::utils::constant_time_eq;
+use crate::crypto::utils::field25519::FieldElement;
+use crate::error::{Error, Result};
+use crate::hashes::hkdf;
+use crate::hashes::sha2::SHA256;
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+/// The u-coordinate of the Curve25519 base point.
+pub const BASE_POINT: [u8; 32] = [
+    9, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0
+];
+
+fn clamp(mut scalar: [u8; 32]) -> [u8; 32] {
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    return scalar;
+}
+
+/// The X25519 function of RFC 7748 section 5: multiplies the point with
+/// u-coordinate `u` by the clamped `scalar`.
+///
+/// The Montgomery ladder always runs all 255 steps and swaps with
+/// `conditional_swap`, so its timing does not depend on the scalar.
+pub fn x25519(scalar: [u8; 32], u: [u8; 32]) -> [u8; 32] {
+    let scalar = clamp(scalar);
+    let a24 = FieldElement::from_u64(121665);
+    let x_1 = FieldElement::from_bytes(u);
+    let mut x_2 = FieldElement::one();
+    let mut z_2 = FieldElement::zero();
+    let mut x_3 = x_1;
+    let mut z_3 = FieldElement::one();
+    let mut swap: u8 = 0;
+    for t in (0..255).rev() {
+        let k_t = (scalar[t / 8] >> (t % 8)) & 1;
+        swap ^= k_t;
+        FieldElement::conditional_swap(&mut x_2, &mut x_3, swap);
+        FieldElement::conditional_swap(&mut z_2, &mut z_3, swap);
+        swap = k_t;
+
+        let a = x_2 + z_2;
+        let aa = a.square();
+        let b = x_2 - z_2;
+        let bb = b.square();
+        let e = aa - bb;
+        let c = x_3 + z_3;
+        let d = x_3 - z_3;
+        let da = d * a;
+        let cb = c * b;
+        x_3 = (da + cb).square();
+        z_3 = x_1 * (da - cb).square();
+        x_2 = aa * bb;
+        z_2 = e * (aa + a24 * e);
+    }
+    FieldElement::conditional_swap(&mut x_2, &mut x_3, swap);
+    FieldElement::conditional_swap(&mut z_2, &mut z_3, swap);
+    (x_2 * z_2.invert()).to_bytes()
+}
+
+/// An X25519 key pair for Diffie-Hellman key agreement.
+pub struct X25519 {
+    secret: [u8; 32],
+}
+
+impl X25519 {
+    pub fn public_key(&self) -> [u8; 32] {
+        x25519(self.secret, BASE_POINT)
+    }
+
+    /// The raw shared secret with `peer_public_key`. Fails with
+    /// `InvalidPublicKey` when the result is all zeros, which happens for
+    /// low-order points (RFC 7748 section 6.1).
+    pub fn diffie_hellman(&self, peer_public_key: &[u8; 32]) -> Result<[u8; 32]> {
+        let shared_secret = x25519(self.secret, *peer_public_key);
+        if constant_time_eq(&shared_secret, &[0; 32]) {
+            return Err(Error::InvalidPublicKey);
+        }
+        Ok(shared_secret)
+    }
+
+    /// Derives a `Chacha20Poly1305` key from the shared secret with HKDF-SHA256,
+    /// binding both public keys and the caller's `info` into the derivation.
+    /// Both sides must agree on which of them is the initiator.
+    pub fn derive_aead(&self, peer_public_key: &[u8; 32], initiator: bool, info: &[u8]) -> Result<Chacha20Poly1305> {
+        let shared_secret = self.diffie_hellman(peer_public_key)?;
+        let mut salt: Vec<u8> = Vec::new();
+        if initiator {
+            salt.extend_from_slice(&self.public_key());
+            salt.extend_from_slice(peer_public_key);
+        } else {
+            salt.extend_from_slice(peer_public_key);
+            salt.extend_from_slice(&self.public_key());
+        }
+        let prk = hkdf::extract::<SHA256>(&salt, &shared_secret);
+        let key: [u8; 32] = hkdf::expand::<SHA256>(&prk, info, 32)?.as_slice().try_into().map_err(|_| Error::InvalidKeyLength)?;
+        Ok(Chacha20Poly1305::new(key))
+    }
+
+    pub fn new_from_slice(secret: &[u8]) -> Result<X25519> {
+        let secret: [u8; 32] = secret.try_into().map_err(|_| Error::InvalidKeyLength)?;
+        Ok(X25519::new(secret))
+    }
+
+    /// `secret` must be 32 uniformly random bytes; clamping happens on use.
+    pub fn new(secret: [u8; 32]) -> X25519 {
+        X25519 {
+            secret: secret,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+
+    fn hex_32(hex: &str) -> [u8; 32] {
+        hex_to_bytes(hex).as_slice().try_into().unwrap()
+    }
+
+    // RFC 7748 section 5.2.
+    #[test]
+    fn x25519_test_vectors() {
+        let scalar = hex_32("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+        let u = hex_32("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c");
+        assert_eq!(x25519(scalar, u), hex_32("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552"));
+
+        // The top bit of this u-coordinate is set and must be ignored.
+        let scalar = hex_32("4b66e9d4d1b4673c5ad22691957d6af5c11b6421e0ea01d42ca4169e7918ba0d");
+        let u = hex_32("e5210f12786811d3f4b7959d0538ae2c31dbe7106fc03c3efc4cd549c715a493");
+        assert_eq!(x25519(scalar, u), hex_32("95cbde9476e8907d7aade45cb4b873f88b595a68799fa152e6f8f7647aac7957"));
+    }
+
+    // RFC 7748 section 5.2: k and u both start as the base point, then each
+    // step computes x25519(k, u) and sets u to the old k.
+    #[test]
+    fn x25519_iterated() {
+        let mut k = BASE_POINT;
+        let mut u = BASE_POINT;
+        for i in 1..=1000 {
+            let result = x25519(k, u);
+            u = k;
+            k = result;
+            if i == 1 {
+                assert_eq!(k, hex_32("422c8e7a6227d7bca1350b3e2bb7279f7897b87bb6854b783c60e80311ae3079"));
+            }
+        }
+        assert_eq!(k, hex_32("684cf59ba83309552800ef566f2f4d3c1c3887c49360e3875f2eb94d99532c51"));
+    }
+
+    // RFC 7748 section 6.1.
+    #[test]
+    fn diffie_hellman_test_vector() {
+        let alice = X25519::new(hex_32("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a"));
+        let bob = X25519::new(hex_32("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb"));
+        assert_eq!(alice.public_key(), hex_32("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"));
+        assert_eq!(bob.public_key(), hex_32("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f"));
+        let shared_secret = hex_32("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+        assert_eq!(alice.diffie_hellman(&bob.public_key()).unwrap(), shared_secret);
+        assert_eq!(bob.diffie_hellman(&alice.public_key()).unwrap(), shared_secret);
+    }
+
+    #[test]
+    fn rejects_low_order_points() {
+        let alice = X25519::new([7; 32]);
+        assert_eq!(alice.diffie_hellman(&[0; 32]), Err(Error::InvalidPublicKey));
+        let mut one: [u8; 32] = [0; 32];
+        one[0] = 1;
+        assert_eq!(alice.diffie_hellman(&one), Err(Error::InvalidPublicKey));
+    }
+
+    #[test]
+    fn derived_keys_match() {
+        let alice = X25519::new([1; 32]);
+        let bob = X25519::new([2; 32]);
+        let alice_aead = alice.derive_aead(&bob.public_key(), true, b"test").unwrap();
+        let bob_aead = bob.derive_aead(&alice.public_key(), false, b"test").unwrap();
+        let mut buffer = *b"message";
+        let tag = alice_aead.encrypt_in_place_detached(&[0; 12], &[], &mut buffer).unwrap();
+        assert!(bob_aead.decrypt_in_place_detached(&[0; 12], &[], &mut buffer, &tag).is_ok());
+        assert_eq!(&buffer, b"message");
+    }
+}
@@ -0,0 +1,152 @@
+use crate::crypto::chacha20::hchacha20;
+use crate::crypto::chacha20poly1305::{Buffer, Chacha20Poly1305, Decryptor, Encryptor, Tag};
+use crate::error::{Error, Result};
+
+use core::convert::TryInto;
+use std::vec::Vec;
+
+/// ChaCha20-Poly1305 with a 24-byte nonce (draft-irtf-cfrg-xchacha).
+///
+/// The first 16 bytes of the nonce derive a subkey through HChaCha20, and the
+/// remaining 8 bytes form the ChaCha20 nonce, so nonces can be chosen at
+/// random without a meaningful chance of collision.
+pub struct XChaCha20Poly1305 {
+    key: [u8; 32],
+}
+
+impl XChaCha20Poly1305 {
+    pub fn encrypt(&self, nonce: &[u8; 24], aad: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, Tag)> {
+        let (aead, chacha_nonce) = self.derive(nonce);
+        aead.encrypt(&chacha_nonce, aad, plaintext)
+    }
+
+    pub fn decrypt(&self, nonce: &[u8; 24], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+        let (aead, chacha_nonce) = self.derive(nonce);
+        aead.decrypt(&chacha_nonce, aad, ciphertext, tag)
+    }
+
+    pub fn encrypt_in_place<B: Buffer>(&self, nonce: &[u8; 24], aad: &[u8], buffer: &mut B) -> Result<()> {
+        let (aead, chacha_nonce) = self.derive(nonce);
+        aead.encrypt_in_place(&chacha_nonce, aad, buffer)
+    }
+
+    pub fn decrypt_in_place<B: Buffer>(&self, nonce: &[u8; 24], aad: &[u8], buffer: &mut B) -> Result<()> {
+        let (aead, chacha_nonce) = self.derive(nonce);
+        aead.decrypt_in_place(&chacha_nonce, aad, buffer)
+    }
+
+    pub fn encrypt_in_place_detached(&self, nonce: &[u8; 24], aad: &[u8], buffer: &mut [u8]) -> Result<Tag> {
+        let (aead, chacha_nonce) = self.derive(nonce);
+        aead.encrypt_in_place_detached(&chacha_nonce, aad, buffer)
+    }
+
+    pub fn decrypt_in_place_detached(&self, nonce: &[u8; 24], aad: &[u8], buffer: &mut [u8], tag: &Tag) -> Result<()> {
+        let (aead, chacha_nonce) = self.derive(nonce);
+        aead.decrypt_in_place_detached(&chacha_nonce, aad, buffer, tag)
+    }
+
+    pub fn begin_encrypt(&self, nonce: &[u8; 24]) -> Result<Encryptor> {
+        let (aead, chacha_nonce) = self.derive(nonce);
+        aead.begin_encrypt(&chacha_nonce)
+    }
+
+    pub fn begin_decrypt(&self, nonce: &[u8; 24]) -> Result<Decryptor> {
+        let (aead, chacha_nonce) = self.derive(nonce);
+        aead.begin_decrypt(&chacha_nonce)
+    }
+
+    fn derive(&self, nonce: &[u8; 24]) -> (Chacha20Poly1305, [u8; 12]) {
+        let mut hchacha_input: [u8; 16] = [0; 16];
+        let mut chacha_nonce: [u8; 12] = [0; 12];
+        hchacha_input.copy_from_slice(&nonce[0..16]);
+        chacha_nonce[4..12].copy_from_slice(&nonce[16..24]);
+        let subkey = hchacha20(self.key, hchacha_input);
+        (Chacha20Poly1305::new(subkey), chacha_nonce)
+    }
+
+    pub fn new_from_slice(key: &[u8]) -> Result<XChaCha20Poly1305> {
+        let key: [u8; 32] = key.try_into().map_err(|_| Error::InvalidKeyLength)?;
+        Ok(XChaCha20Poly1305::new(key))
+    }
+
+    pub fn new(key: [u8; 32]) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305 {
+            key: key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+
+    fn key() -> [u8; 32] {
+        let mut key: [u8; 32] = [0; 32];
+        for i in 0..32 {
+            key[i] = 0x80 + i as u8;
+        }
+        return key;
+    }
+
+    fn nonce() -> [u8; 24] {
+        let mut nonce: [u8; 24] = [0; 24];
+        for i in 0..24 {
+            nonce[i] = 0x40 + i as u8;
+        }
+        return nonce;
+    }
+
+    const PLAINTEXT: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+    // draft-irtf-cfrg-xchacha-03 section 2.2.1.
+    #[test]
+    fn hchacha20_test_vector() {
+        let mut key: [u8; 32] = [0; 32];
+        for i in 0..32 {
+            key[i] = i as u8;
+        }
+        let input: [u8; 16] = hex_to_bytes("000000090000004a0000000031415927").as_slice().try_into().unwrap();
+        let expected = hex_to_bytes("82413b4227b27bfed30e42508a877d73a0f9e4d58a74a853c12ec41326d3ecdc");
+        assert_eq!(hchacha20(key, input).to_vec(), expected);
+    }
+
+    // draft-irtf-cfrg-xchacha-03 appendix A.3.1.
+    #[test]
+    fn aead_test_vector() {
+        let aad = hex_to_bytes("50515253c0c1c2c3c4c5c6c7");
+        let expected_ciphertext = hex_to_bytes("
+            bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb
+            731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b452
+            2f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff9
+            21f9664c97637da9768812f615c68b13b52e");
+        let expected_tag = hex_to_bytes("c0875924c1c7987947deafd8780acf49");
+
+        let aead = XChaCha20Poly1305::new(key());
+        let (ciphertext, tag) = aead.encrypt(&nonce(), &aad, PLAINTEXT).unwrap();
+        assert_eq!(ciphertext, expected_ciphertext);
+        assert_eq!(tag.to_vec(), expected_tag);
+        assert_eq!(aead.decrypt(&nonce(), &aad, &ciphertext, &tag).unwrap(), PLAINTEXT);
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let aad = hex_to_bytes("50515253c0c1c2c3c4c5c6c7");
+        let aead = XChaCha20Poly1305::new(key());
+        let (ciphertext, tag) = aead.encrypt(&nonce(), &aad, PLAINTEXT).unwrap();
+
+        let mut bad_ciphertext = ciphertext.clone();
+        bad_ciphertext[0] ^= 1;
+        assert_eq!(aead.decrypt(&nonce(), &aad, &bad_ciphertext, &tag), Err(Error::AuthenticationFailed));
+
+        let mut bad_tag = tag;
+        bad_tag[15] ^= 0x80;
+        assert_eq!(aead.decrypt(&nonce(), &aad, &ciphertext, &bad_tag), Err(Error::AuthenticationFailed));
+
+        assert_eq!(aead.decrypt(&nonce(), &aad[1..], &ciphertext, &tag), Err(Error::AuthenticationFailed));
+
+        let mut bad_nonce = nonce();
+        bad_nonce[3] ^= 1;
+        assert_eq!(aead.decrypt(&bad_nonce, &aad, &ciphertext, &tag), Err(Error::AuthenticationFailed));
+    }
+}
@@ -0,0 +1,92 @@
+use core::fmt;
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum Error {
+    /// The tag did not match the ciphertext and associated data.
+    AuthenticationFailed,
+    /// The ChaCha20 block counter would wrap.
+    CounterExhausted,
+    /// The message cannot be encrypted without exhausting the block counter.
+    MessageTooLong,
+    /// AAD was supplied after message data in an incremental operation.
+    AadAfterMessage,
+    /// A nonce sequence has no unused values left.
+    NonceExhausted,
+    /// A segmented stream ended before its final segment.
+    TruncatedStream,
+    /// A segment was supplied after the final segment of a stream.
+    StreamFinished,
+    /// A segment size of zero was requested.
+    InvalidSegmentSize,
+    /// A packet is too short or its length field does not match its size.
+    InvalidPacketLength,
+    /// A TLS record exceeds the size limits of RFC 8446 section 5.
+    RecordOverflow,
+    /// A TLS record header or length is malformed.
+    InvalidRecord,
+    /// A record or message has the wrong type or is addressed elsewhere.
+    UnexpectedMessage,
+    /// A WireGuard counter was already received or is outside the replay window.
+    ReplayedCounter,
+    /// A public key does not decode to a usable curve point.
+    InvalidPublicKey,
+    /// A signature is malformed or does not verify.
+    InvalidSignature,
+    /// A handshake message was sent out of turn or a required key is missing.
+    InvalidHandshakeState,
+    /// A fast key erasure generator was asked to change its position or stream.
+    KeyErased,
+    /// HPKE PSK inputs do not match the mode: both or neither must be given.
+    InvalidPskInputs,
+    /// A key was supplied with the wrong number of bytes.
+    InvalidKeyLength,
+    /// A nonce was supplied with the wrong number of bytes.
+    InvalidNonceLength,
+    /// A tag was supplied with the wrong number of bytes.
+    InvalidTagLength,
+    /// More key material was requested than HKDF-Expand can produce.
+    InvalidOutputLength,
+    /// A string could not be parsed as a hexadecimal U256.
+    InvalidHexString,
+    /// A U256 value does not fit in the requested integer type.
+    Overflow,
+    /// A U256 value was divided by zero.
+    DivisionByZero,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Error::AuthenticationFailed => "authentication failed",
+            Error::CounterExhausted => "block counter exhausted",
+            Error::MessageTooLong => "message too long",
+            Error::AadAfterMessage => "AAD supplied after message data",
+            Error::NonceExhausted => "nonce sequence exhausted",
+            Error::TruncatedStream => "stream ended before its final segment",
+            Error::StreamFinished => "segment after the final segment",
+            Error::InvalidSegmentSize => "invalid segment size",
+            Error::InvalidPacketLength => "invalid packet length",
+            Error::RecordOverflow => "record overflow",
+            Error::InvalidRecord => "invalid record",
+            Error::UnexpectedMessage => "unexpected message",
+            Error::ReplayedCounter => "replayed counter",
+            Error::InvalidPublicKey => "invalid public key",
+            Error::InvalidSignature => "invalid signature",
+            Error::InvalidHandshakeState => "invalid handshake state",
+            Error::KeyErased => "key erased",
+            Error::InvalidPskInputs => "invalid PSK inputs",
+            Error::InvalidKeyLength => "invalid key length",
+            Error::InvalidNonceLength => "invalid nonce length",
+            Error::InvalidTagLength => "invalid tag length",
+            Error::InvalidOutputLength => "invalid output length",
+            Error::InvalidHexString => "invalid hex string",
+            Error::Overflow => "value out of range",
+            Error::DivisionByZero => "division by zero",
+        };
+        f.write_str(description)
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = core::result::Result<T, Error>;
@@ -0,0 +1,84 @@
+use crate::error::{Error, Result};
+use crate::hashes::Digest;
+use crate::hashes::hmac::{hmac, Hmac};
+
+use std::vec::Vec;
+
+/// HKDF-Extract (RFC 5869 section 2.2). An empty salt is treated as a string
+/// of zeros the length of the hash output.
+pub fn extract<D: Digest>(salt: &[u8], input_key_material: &[u8]) -> Vec<u8> {
+    if salt.is_empty() {
+        return hmac::<D>(&vec![0; D::OUTPUT_SIZE], input_key_material);
+    }
+    return hmac::<D>(salt, input_key_material);
+}
+
+/// HKDF-Expand (RFC 5869 section 2.3), producing at most 255 hash outputs.
+pub fn expand<D: Digest>(pseudorandom_key: &[u8], info: &[u8], length: usize) -> Result<Vec<u8>> {
+    if length > 255 * D::OUTPUT_SIZE {
+        return Err(Error::InvalidOutputLength);
+    }
+    let mut output: Vec<u8> = Vec::new();
+    let mut previous_block: Vec<u8> = Vec::new();
+    let mut counter: u8 = 1;
+    while output.len() < length {
+        let mut mac = Hmac::<D>::new(pseudorandom_key);
+        mac.update(&previous_block);
+        mac.update(info);
+        mac.update(&[counter]);
+        previous_block = mac.finalize();
+        output.extend_from_slice(&previous_block);
+        counter = counter.wrapping_add(1);
+    }
+    output.truncate(length);
+    return Ok(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+    use crate::hashes::sha2::SHA256;
+
+    // RFC 5869 appendix A, test cases 1 to 3 (the SHA-256 ones).
+    #[test]
+    fn rfc_5869_test_vectors() {
+        let vectors: [(&str, &str, &str, &str, &str); 3] = [
+            (
+                "0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b",
+                "000102030405060708090a0b0c",
+                "f0f1f2f3f4f5f6f7f8f9",
+                "077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5",
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865",
+            ),
+            (
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f404142434445464748494a4b4c4d4e4f",
+                "606162636465666768696a6b6c6d6e6f707172737475767778797a7b7c7d7e7f808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9fa0a1a2a3a4a5a6a7a8a9aaabacadaeaf",
+                "b0b1b2b3b4b5b6b7b8b9babbbcbdbebfc0c1c2c3c4c5c6c7c8c9cacbcccdcecfd0d1d2d3d4d5d6d7d8d9dadbdcdddedfe0e1e2e3e4e5e6e7e8e9eaebecedeeeff0f1f2f3f4f5f6f7f8f9fafbfcfdfeff",
+                "06a6b88c5853361a06104c9ceb35b45cef760014904671014a193f40c15fc244",
+                "b11e398dc80327a1c8e7f78c596a49344f012eda2d4efad8a050cc4c19afa97c59045a99cac7827271cb41c65e590e09da3275600c2f09b8367793a9aca3db71cc30c58179ec3e87c14c01d5c1f3434f1d87",
+            ),
+            (
+                "0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b",
+                "",
+                "",
+                "19ef24a32c717b167f33a91d6f648bdf96596776afdb6377ac434c1c293ccb04",
+                "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8",
+            ),
+        ];
+        for (input_key_material, salt, info, pseudorandom_key, output_key_material) in vectors.iter() {
+            let prk = extract::<SHA256>(&hex_to_bytes(salt), &hex_to_bytes(input_key_material));
+            assert_eq!(prk, hex_to_bytes(pseudorandom_key));
+            let okm = hex_to_bytes(output_key_material);
+            assert_eq!(expand::<SHA256>(&prk, &hex_to_bytes(info), okm.len()).unwrap(), okm);
+        }
+    }
+
+    #[test]
+    fn output_length_limit() {
+        let prk = extract::<SHA256>(b"salt", b"input key material");
+        assert_eq!(expand::<SHA256>(&prk, b"", 255 * 32).unwrap().len(), 255 * 32);
+        assert_eq!(expand::<SHA256>(&prk, b"", 255 * 32 + 1), Err(Error::InvalidOutputLength));
+        assert!(expand::<SHA256>(&prk, b"", 0).unwrap().is_empty());
+    }
+}
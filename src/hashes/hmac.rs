@@ -0,0 +1,118 @@
+use crate::hashes::Digest;
+
+use std::vec::Vec;
+
+/// HMAC (RFC 2104) over any [`Digest`].
+pub struct Hmac<D: Digest> {
+    inner: D,
+    outer_key_pad: Vec<u8>
+}
+
+impl<D: Digest> Hmac<D> {
+    pub fn update(&mut self, input_bytes: &[u8]) {
+        self.inner.update(input_bytes);
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        let mut outer = D::new();
+        outer.update(&self.outer_key_pad);
+        outer.update(&self.inner.finalize());
+        return outer.finalize();
+    }
+
+    pub fn new(key: &[u8]) -> Hmac<D> {
+        let mut block_key: Vec<u8> = if key.len() > D::BLOCK_SIZE {
+            D::hash(key)
+        } else {
+            key.to_vec()
+        };
+        block_key.resize(D::BLOCK_SIZE, 0);
+        let inner_key_pad: Vec<u8> = block_key.iter().map(|x| x ^ 0x36).collect();
+        let outer_key_pad: Vec<u8> = block_key.iter().map(|x| x ^ 0x5c).collect();
+        let mut inner = D::new();
+        inner.update(&inner_key_pad);
+        Hmac {
+            inner: inner,
+            outer_key_pad: outer_key_pad
+        }
+    }
+}
+
+pub fn hmac<D: Digest>(key: &[u8], input_bytes: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<D>::new(key);
+    mac.update(input_bytes);
+    return mac.finalize();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+    use crate::hashes::sha2::{SHA256, SHA512};
+
+    // RFC 4231 section 4: key, data, HMAC-SHA-256, HMAC-SHA-512.
+    const RFC_4231_VECTORS: [(&[u8], &[u8], &str, &str); 6] = [
+        (
+            &[0x0b; 20],
+            b"Hi There",
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cdedaa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854",
+        ),
+        (
+            b"Jefe",
+            b"what do ya want for nothing?",
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+        ),
+        (
+            &[0xaa; 20],
+            &[0xdd; 50],
+            "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+            "fa73b0089d56a284efb0f0756c890be9b1b5dbdd8ee81a3655f83e33b2279d39bf3e848279a722c806b485a47e67c807b946a337bee8942674278859e13292fb",
+        ),
+        (
+            &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25],
+            &[0xcd; 50],
+            "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            "b0ba465637458c6990e5a8c5f61d4af7e576d97ff94b872de76f8050361ee3dba91ca5c11aa25eb4d679275cc5788063a5f19741120c4f2de2adebeb10a298dd",
+        ),
+        (
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            "80b24263c7c1a3ebb71493c1dd7be8b49b46d1f41b4aeec1121b013783f8f3526b56d037e05f2598bd0fd2215d6a1e5295e64f73f63f0aec8b915a985d786598",
+        ),
+        (
+            &[0xaa; 131],
+            b"This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm.",
+            "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            "e37b6a775dc87dbaa4dfa9f96e5e3ffddebd71f8867289865df5a32d20cdc944b6022cac3c4982b10d5eeb55c3e4de15134676fb6de0446065c97440fa8c6a58",
+        ),
+    ];
+
+    #[test]
+    fn rfc_4231_test_vectors() {
+        for (key, data, sha256, sha512) in RFC_4231_VECTORS.iter() {
+            assert_eq!(hmac::<SHA256>(key, data), hex_to_bytes(sha256));
+            assert_eq!(hmac::<SHA512>(key, data), hex_to_bytes(sha512));
+        }
+    }
+
+    #[test]
+    fn rfc_4231_truncated_output() {
+        // Test case 5 only specifies the first 128 bits.
+        let key: [u8; 20] = [0x0c; 20];
+        assert_eq!(hmac::<SHA256>(&key, b"Test With Truncation")[..16].to_vec(), hex_to_bytes("a3b6167473100ee06e0c796c2955552b"));
+        assert_eq!(hmac::<SHA512>(&key, b"Test With Truncation")[..16].to_vec(), hex_to_bytes("415fad6271580a531d4179bc891d87a6"));
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let (key, data, sha256, _) = RFC_4231_VECTORS[5];
+        let mut mac = Hmac::<SHA256>::new(key);
+        for chunk in data.chunks(7) {
+            mac.update(chunk);
+        }
+        assert_eq!(mac.finalize(), hex_to_bytes(sha256));
+    }
+}
@@ -1,5 +1,27 @@
+pub mod hkdf;
+pub mod hmac;
+pub mod sha2;
+
 use std::vec::Vec;
 
+/// A hash function that HMAC and HKDF can be built on.
+pub trait Digest {
+    const BLOCK_SIZE: usize;
+    const OUTPUT_SIZE: usize;
+
+    fn new() -> Self;
+
+    fn update(&mut self, input_bytes: &[u8]);
+
+    fn finalize(self) -> Vec<u8>;
+
+    fn hash(input_bytes: &[u8]) -> Vec<u8> where Self: Sized {
+        let mut hasher = Self::new();
+        hasher.update(input_bytes);
+        hasher.finalize()
+    }
+}
+
 pub struct SHA3 {
     input_bytes: Vec<u8>,
     current_state: [[u64; 5]; 5],
@@ -92,10 +114,10 @@ impl SHA3 {
 
     fn pad_vector(&mut self) {
         let b = self.block_size + self.c;
-        let padding_bytes_required = ((self.input_bytes.len() as i64) * -1).rem_euclid((b / 8) as i64);
+        let padding_bytes_required = (-(self.input_bytes.len() as i64)).rem_euclid((b / 8) as i64);
         if padding_bytes_required > 1 {
             self.input_bytes.push(0x80);
-            while (((self.input_bytes.len() as i64) * -1).rem_euclid((b / 8) as i64)) > 1 {
+            while (-(self.input_bytes.len() as i64)).rem_euclid((b / 8) as i64) > 1 {
                 self.input_bytes.push(0x00);
             }
             self.input_bytes.push(0x01);
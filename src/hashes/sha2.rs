@@ -0,0 +1,265 @@
+use crate::hashes::Digest;
+
+use std::vec::Vec;
+
+/// SHA-256 (FIPS 180-4).
+pub struct SHA256 {
+    current_state: [u32; 8],
+    buffer: [u8; 64],
+    buffer_length: usize,
+    message_length: u64
+}
+
+impl SHA256 {
+
+    const ROUND_CONSTANTS: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+        0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+        0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+        0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+        0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+        0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+        0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2
+    ];
+
+    const INITIAL_STATE: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+        0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19
+    ];
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w: [u32; 64] = [0; 64];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([block[i * 4], block[i * 4 + 1], block[i * 4 + 2], block[i * 4 + 3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let mut state = self.current_state;
+        for i in 0..64 {
+            let s1 = state[4].rotate_right(6) ^ state[4].rotate_right(11) ^ state[4].rotate_right(25);
+            let ch = (state[4] & state[5]) ^ (!state[4] & state[6]);
+            let temp1 = state[7].wrapping_add(s1).wrapping_add(ch).wrapping_add(Self::ROUND_CONSTANTS[i]).wrapping_add(w[i]);
+            let s0 = state[0].rotate_right(2) ^ state[0].rotate_right(13) ^ state[0].rotate_right(22);
+            let maj = (state[0] & state[1]) ^ (state[0] & state[2]) ^ (state[1] & state[2]);
+            let temp2 = s0.wrapping_add(maj);
+            state = [
+                temp1.wrapping_add(temp2), state[0], state[1], state[2],
+                state[3].wrapping_add(temp1), state[4], state[5], state[6]
+            ];
+        }
+        for i in 0..8 {
+            self.current_state[i] = self.current_state[i].wrapping_add(state[i]);
+        }
+    }
+
+    pub fn update(&mut self, input_bytes: &[u8]) {
+        self.message_length = self.message_length.wrapping_add(input_bytes.len() as u64);
+        for value in input_bytes.iter() {
+            self.buffer[self.buffer_length] = *value;
+            self.buffer_length += 1;
+            if self.buffer_length == 64 {
+                let block = self.buffer;
+                self.compress(&block);
+                self.buffer_length = 0;
+            }
+        }
+    }
+
+    pub fn digest(mut self) -> [u8; 32] {
+        let bit_length = self.message_length.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.buffer_length != 56 {
+            self.update(&[0x00]);
+        }
+        self.update(&bit_length.to_be_bytes());
+        let mut digest_output: [u8; 32] = [0; 32];
+        for i in 0..8 {
+            digest_output[i * 4..i * 4 + 4].copy_from_slice(&self.current_state[i].to_be_bytes());
+        }
+        return digest_output;
+    }
+
+    pub fn new() -> SHA256 {
+        SHA256 {
+            current_state: Self::INITIAL_STATE,
+            buffer: [0; 64],
+            buffer_length: 0,
+            message_length: 0
+        }
+    }
+}
+
+impl Digest for SHA256 {
+    const BLOCK_SIZE: usize = 64;
+    const OUTPUT_SIZE: usize = 32;
+
+    fn new() -> SHA256 {
+        SHA256::new()
+    }
+
+    fn update(&mut self, input_bytes: &[u8]) {
+        SHA256::update(self, input_bytes);
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        self.digest().to_vec()
+    }
+}
+
+/// SHA-512 (FIPS 180-4).
+pub struct SHA512 {
+    current_state: [u64; 8],
+    buffer: [u8; 128],
+    buffer_length: usize,
+    message_length: u128
+}
+
+impl SHA512 {
+
+    const ROUND_CONSTANTS: [u64; 80] = [
+        0x428a2f98d728ae22, 0x7137449123ef65cd, 0xb5c0fbcfec4d3b2f, 0xe9b5dba58189dbbc, 0x3956c25bf348b538, 0x59f111f1b605d019, 0x923f82a4af194f9b, 0xab1c5ed5da6d8118,
+        0xd807aa98a3030242, 0x12835b0145706fbe, 0x243185be4ee4b28c, 0x550c7dc3d5ffb4e2, 0x72be5d74f27b896f, 0x80deb1fe3b1696b1, 0x9bdc06a725c71235, 0xc19bf174cf692694,
+        0xe49b69c19ef14ad2, 0xefbe4786384f25e3, 0x0fc19dc68b8cd5b5, 0x240ca1cc77ac9c65, 0x2de92c6f592b0275, 0x4a7484aa6ea6e483, 0x5cb0a9dcbd41fbd4, 0x76f988da831153b5,
+        0x983e5152ee66dfab, 0xa831c66d2db43210, 0xb00327c898fb213f, 0xbf597fc7beef0ee4, 0xc6e00bf33da88fc2, 0xd5a79147930aa725, 0x06ca6351e003826f, 0x142929670a0e6e70,
+        0x27b70a8546d22ffc, 0x2e1b21385c26c926, 0x4d2c6dfc5ac42aed, 0x53380d139d95b3df, 0x650a73548baf63de, 0x766a0abb3c77b2a8, 0x81c2c92e47edaee6, 0x92722c851482353b,
+        0xa2bfe8a14cf10364, 0xa81a664bbc423001, 0xc24b8b70d0f89791, 0xc76c51a30654be30, 0xd192e819d6ef5218, 0xd69906245565a910, 0xf40e35855771202a, 0x106aa07032bbd1b8,
+        0x19a4c116b8d2d0c8, 0x1e376c085141ab53, 0x2748774cdf8eeb99, 0x34b0bcb5e19b48a8, 0x391c0cb3c5c95a63, 0x4ed8aa4ae3418acb, 0x5b9cca4f7763e373, 0x682e6ff3d6b2b8a3,
+        0x748f82ee5defb2fc, 0x78a5636f43172f60, 0x84c87814a1f0ab72, 0x8cc702081a6439ec, 0x90befffa23631e28, 0xa4506cebde82bde9, 0xbef9a3f7b2c67915, 0xc67178f2e372532b,
+        0xca273eceea26619c, 0xd186b8c721c0c207, 0xeada7dd6cde0eb1e, 0xf57d4f7fee6ed178, 0x06f067aa72176fba, 0x0a637dc5a2c898a6, 0x113f9804bef90dae, 0x1b710b35131c471b,
+        0x28db77f523047d84, 0x32caab7b40c72493, 0x3c9ebe0a15c9bebc, 0x431d67c49c100d4c, 0x4cc5d4becb3e42b6, 0x597f299cfc657e2a, 0x5fcb6fab3ad6faec, 0x6c44198c4a475817
+    ];
+
+    const INITIAL_STATE: [u64; 8] = [
+        0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+        0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179
+    ];
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w: [u64; 80] = [0; 80];
+        for i in 0..16 {
+            let mut word_bytes: [u8; 8] = [0; 8];
+            word_bytes.copy_from_slice(&block[i * 8..i * 8 + 8]);
+            w[i] = u64::from_be_bytes(word_bytes);
+        }
+        for i in 16..80 {
+            let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+            let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let mut state = self.current_state;
+        for i in 0..80 {
+            let s1 = state[4].rotate_right(14) ^ state[4].rotate_right(18) ^ state[4].rotate_right(41);
+            let ch = (state[4] & state[5]) ^ (!state[4] & state[6]);
+            let temp1 = state[7].wrapping_add(s1).wrapping_add(ch).wrapping_add(Self::ROUND_CONSTANTS[i]).wrapping_add(w[i]);
+            let s0 = state[0].rotate_right(28) ^ state[0].rotate_right(34) ^ state[0].rotate_right(39);
+            let maj = (state[0] & state[1]) ^ (state[0] & state[2]) ^ (state[1] & state[2]);
+            let temp2 = s0.wrapping_add(maj);
+            state = [
+                temp1.wrapping_add(temp2), state[0], state[1], state[2],
+                state[3].wrapping_add(temp1), state[4], state[5], state[6]
+            ];
+        }
+        for i in 0..8 {
+            self.current_state[i] = self.current_state[i].wrapping_add(state[i]);
+        }
+    }
+
+    pub fn update(&mut self, input_bytes: &[u8]) {
+        self.message_length = self.message_length.wrapping_add(input_bytes.len() as u128);
+        for value in input_bytes.iter() {
+            self.buffer[self.buffer_length] = *value;
+            self.buffer_length += 1;
+            if self.buffer_length == 128 {
+                let block = self.buffer;
+                self.compress(&block);
+                self.buffer_length = 0;
+            }
+        }
+    }
+
+    pub fn digest(mut self) -> [u8; 64] {
+        let bit_length = self.message_length.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.buffer_length != 112 {
+            self.update(&[0x00]);
+        }
+        self.update(&bit_length.to_be_bytes());
+        let mut digest_output: [u8; 64] = [0; 64];
+        for i in 0..8 {
+            digest_output[i * 8..i * 8 + 8].copy_from_slice(&self.current_state[i].to_be_bytes());
+        }
+        return digest_output;
+    }
+
+    pub fn new() -> SHA512 {
+        SHA512 {
+            current_state: Self::INITIAL_STATE,
+            buffer: [0; 128],
+            buffer_length: 0,
+            message_length: 0
+        }
+    }
+}
+
+impl Digest for SHA512 {
+    const BLOCK_SIZE: usize = 128;
+    const OUTPUT_SIZE: usize = 64;
+
+    fn new() -> SHA512 {
+        SHA512::new()
+    }
+
+    fn update(&mut self, input_bytes: &[u8]) {
+        SHA512::update(self, input_bytes);
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        self.digest().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::utils::hex_to_bytes;
+
+    const TWO_BLOCK_MESSAGE: &[u8] = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+
+    #[test]
+    fn sha256_test_vectors() {
+        let vectors: [(&[u8], &str); 3] = [
+            (b"abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (b"", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+            (TWO_BLOCK_MESSAGE, "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"),
+        ];
+        for (message, expected) in vectors.iter() {
+            assert_eq!(SHA256::hash(message), hex_to_bytes(expected));
+            let mut hasher = SHA256::new();
+            for byte in message.iter() {
+                hasher.update(&[*byte]);
+            }
+            assert_eq!(hasher.digest().to_vec(), hex_to_bytes(expected));
+        }
+    }
+
+    #[test]
+    fn sha512_test_vectors() {
+        let vectors: [(&[u8], &str); 3] = [
+            (b"abc", "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"),
+            (b"", "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e"),
+            (TWO_BLOCK_MESSAGE, "204a8fc6dda82f0a0ced7beb8e08a41657c16ef468b228a8279be331a703c33596fd15c13b1b07f9aa1d3bea57789ca031ad85c7a71dd70354ec631238ca3445"),
+        ];
+        for (message, expected) in vectors.iter() {
+            assert_eq!(SHA512::hash(message), hex_to_bytes(expected));
+            let mut hasher = SHA512::new();
+            for byte in message.iter() {
+                hasher.update(&[*byte]);
+            }
+            assert_eq!(hasher.digest().to_vec(), hex_to_bytes(expected));
+        }
+    }
+}
@@ -0,0 +1,22 @@
+#![allow(clippy::needless_return, clippy::needless_range_loop, clippy::manual_memcpy, clippy::redundant_field_names, clippy::new_without_default)]
+
+pub mod crypto;
+pub mod error;
+pub mod hashes;
+
+pub use crate::crypto::chacha20poly1305::{Buffer, Chacha20Poly1305, Decryptor, Encryptor, Tag};
+pub use crate::crypto::chacha20poly1305_committing::Chacha20Poly1305Committing;
+pub use crate::crypto::chacha20poly1305_legacy::Chacha20Poly1305Legacy;
+pub use crate::crypto::chacha20poly1305_siv::Chacha20Poly1305Siv;
+pub use crate::crypto::ed25519::Ed25519;
+pub use crate::crypto::hpke::{ReceiverContext, SenderContext};
+pub use crate::crypto::noise::{CipherState, HandshakePattern, HandshakeState};
+pub use crate::crypto::nonce::{CounterNonce, NonceSequence, PrefixedCounterNonce, XorNonce};
+pub use crate::crypto::openssh::OpenSshChacha20Poly1305;
+pub use crate::crypto::rng::{ChaCha12Rng, ChaCha20Rng, ChaCha8Rng, ChaChaRng, Keystream};
+pub use crate::crypto::stream::{StreamDecryptor, StreamEncryptor};
+pub use crate::crypto::tls13::{ContentType, RecordProtection};
+pub use crate::crypto::wireguard::{TransportReceiver, TransportSender};
+pub use crate::crypto::x25519::X25519;
+pub use crate::crypto::xchacha20poly1305::XChaCha20Poly1305;
+pub use crate::error::{Error, Result};